@@ -1,32 +1,382 @@
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use std::collections::VecDeque;
 use std::io::Cursor;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use arboard::{Clipboard, ImageData};
-use base64::{engine::general_purpose, Engine as _};
 use serde::Serialize;
 use tauri::{AppHandle, Emitter};
+use tracing::{debug, error, warn};
+#[cfg(target_os = "windows")]
+use windows::Win32::System::DataExchange::GetClipboardSequenceNumber;
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
 
-#[derive(Serialize, Clone)]
-struct ClipboardUpdate {
+use crate::history::{self, HistoryItem};
+use crate::settings::{self, RecopyBehavior};
+use crate::{images, status, text};
+
+#[derive(Serialize, Clone, Default)]
+pub struct ClipboardUpdate {
+    /// Id of the history item recorded for this capture. A UUIDv7, so ids
+    /// sort in capture order.
+    id: String,
+    /// Capture time in Unix millis, matching the history item's `timestamp`.
+    timestamp_ms: u64,
     #[serde(rename = "type")]
     item_type: String,
+    /// For images, a downscaled preview when the original is larger than
+    /// `Settings::image_preview_max_px`.
     content: String,
+    /// HTML version of copied text, when the source app provided one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html: Option<String>,
+    /// RTF version of copied text (Word, WordPad), Windows only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rtf: Option<String>,
+    /// Executable of the app the content was copied from, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_app: Option<String>,
+    /// Text as copied, when normalization changed it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    original: Option<String>,
+    /// Unix millis at which a copy from an ignored app expires.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    /// Normalized hex of a `"color"` item; `content` is the color as copied.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    /// Hash of a previewed image, for `get_full_image`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_hash: Option<String>,
+    /// Pixel size of an image, so the UI needn't decode it to show it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+    /// Size of an image's full-resolution PNG.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_bytes: Option<usize>,
+    /// Full-resolution PNG of a previewed image, stored next to history
+    /// rather than sent with the event.
+    #[serde(skip)]
+    full_png: Option<Vec<u8>>,
+    /// Whether `content` is only the start of a text longer than
+    /// `Settings::max_text_length`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    /// Hash of a truncated text, for `get_full_text`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text_hash: Option<String>,
+    /// The whole of a truncated text, stored next to history like
+    /// `full_png`.
+    #[serde(skip)]
+    full_text: Option<String>,
 }
 
-fn image_to_data_url(img: ImageData<'static>) -> Option<(u64, String)> {
-    let mut hasher = DefaultHasher::new();
-    img.width.hash(&mut hasher);
-    img.height.hash(&mut hasher);
-    img.bytes.len().hash(&mut hasher);
-    if !img.bytes.is_empty() {
-        img.bytes[0].hash(&mut hasher);
-        img.bytes[img.bytes.len() / 2].hash(&mut hasher);
-        img.bytes[img.bytes.len() - 1].hash(&mut hasher);
+impl ClipboardUpdate {
+    /// Text capture, tagged `"url"` when it is a single link and `"path"`
+    /// when it names an existing file or folder. With
+    /// `Settings::trim_captured_text` the whitespace is normalized first.
+    /// A lone color code becomes a `"color"` item with its hex form in
+    /// `color`, and a JSON object or array is tagged `"json"`. Text over
+    /// `Settings::max_text_length` is cut to that length, without its HTML
+    /// and RTF flavors.
+    fn text(content: String, html: Option<String>, rtf: Option<String>) -> Self {
+        if let Some(hex) = text::parse_color(&content) {
+            return Self {
+                item_type: "color".to_string(),
+                content,
+                color: Some(hex),
+                ..Self::default()
+            };
+        }
+
+        let (content, original) = if settings::get().trim_captured_text {
+            let normalized = text::normalize_whitespace(&content);
+            if normalized.is_empty() || normalized == content {
+                (content, None)
+            } else {
+                (normalized, Some(content))
+            }
+        } else {
+            (content, None)
+        };
+        let max = settings::get().max_text_length;
+        if let Some((end, _)) = content.char_indices().nth(max).filter(|_| max > 0) {
+            return Self {
+                item_type: "text".to_string(),
+                content: content[..end].to_string(),
+                truncated: true,
+                text_hash: Some(format!("{:016x}", text_hash(&content))),
+                full_text: Some(content),
+                ..Self::default()
+            };
+        }
+        if let Some(path) = existing_path(&content) {
+            return Self {
+                item_type: "path".to_string(),
+                original: original.or_else(|| (path != content).then_some(content)),
+                content: path,
+                html,
+                rtf,
+                ..Self::default()
+            };
+        }
+        let item_type = if is_url(&content) {
+            "url"
+        } else if text::is_json(&content) {
+            "json"
+        } else {
+            "text"
+        };
+        Self {
+            item_type: item_type.to_string(),
+            content,
+            html,
+            rtf,
+            original,
+            ..Self::default()
+        }
+    }
+
+    /// What `read_clipboard` returns when there's nothing it can read.
+    fn empty() -> Self {
+        Self {
+            item_type: "empty".to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Files copied in a file manager; `content` holds one path per line.
+    fn files(paths: String) -> Self {
+        Self {
+            item_type: "files".to_string(),
+            content: paths,
+            ..Self::default()
+        }
+    }
+
+    fn image(encoded: EncodedImage) -> Self {
+        let size_bytes = encoded.png.len();
+        let (content, full_png) = match encoded.preview {
+            Some(preview) => (preview, Some(encoded.png)),
+            None => (
+                images::to_data_url(&encoded.png, images::OutputFormat::Png),
+                None,
+            ),
+        };
+        Self {
+            item_type: "image".to_string(),
+            content,
+            image_hash: full_png.is_some().then(|| format!("{:016x}", encoded.hash)),
+            width: Some(encoded.width),
+            height: Some(encoded.height),
+            size_bytes: Some(size_bytes),
+            full_png,
+            ..Self::default()
+        }
+    }
+}
+
+/// Whether `text` is nothing but an http(s)/ftp URL. A prefix and shape
+/// check only, since it runs on every copy.
+fn is_url(text: &str) -> bool {
+    let text = text.trim();
+    if text.chars().any(char::is_whitespace) {
+        return false;
+    }
+    let Some((scheme, rest)) = text.split_once("://") else {
+        return false;
+    };
+    if !["http", "https", "ftp"]
+        .iter()
+        .any(|s| scheme.eq_ignore_ascii_case(s))
+    {
+        return false;
+    }
+    let host = rest.split(['/', '?', '#']).next().unwrap_or("");
+    !host.is_empty() && !host.starts_with(['.', ':', '@'])
+}
+
+// Longer text is never taken for a path, sparing the filesystem lookup.
+const MAX_PATH_BYTES: usize = 1024;
+
+/// The absolute path `text` is, when it's a single line naming a file or
+/// folder that exists. Quotes around it, as Explorer's "Copy as path" adds,
+/// are dropped.
+fn existing_path(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.len() > MAX_PATH_BYTES || text.contains(['\n', '\r']) {
+        return None;
+    }
+    let text = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text);
+    // UNC paths are skipped: checking one can stall for seconds on an
+    // unreachable server, and this runs on every copy.
+    if text.starts_with(r"\\") {
+        return None;
+    }
+    let path = Path::new(text);
+    (path.is_absolute() && path.exists()).then(|| text.to_string())
+}
+
+/// Strips the CF_HTML description header (`Version:`, `StartHTML:` offsets,
+/// ...) and surrounding document, keeping what's between the
+/// `<!--StartFragment-->` and `<!--EndFragment-->` markers. Input without
+/// markers is returned as-is.
+fn html_fragment(raw: &str) -> &str {
+    const START: &str = "<!--StartFragment-->";
+    const END: &str = "<!--EndFragment-->";
+
+    let Some(start) = raw.find(START).map(|i| i + START.len()) else {
+        return raw;
+    };
+    let end = raw[start..].find(END).map_or(raw.len(), |i| start + i);
+    &raw[start..end]
+}
+
+/// HTML flavor of the current clipboard content, if any.
+fn read_html(clipboard: &mut Clipboard) -> Option<String> {
+    let raw = clipboard.get().html().ok()?;
+    let fragment = html_fragment(&raw).trim();
+    (!fragment.is_empty()).then(|| fragment.to_string())
+}
+
+const DEFAULT_POLL_INTERVAL_MS: u64 = 500;
+const MIN_POLL_INTERVAL_MS: u64 = 50;
+const MAX_POLL_INTERVAL_MS: u64 = 5000;
+
+static POLL_INTERVAL_MS: AtomicU64 = AtomicU64::new(DEFAULT_POLL_INTERVAL_MS);
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+// How long after one of our own writes a matching clipboard change is
+// treated as its echo rather than a new copy.
+const SELF_WRITE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Bumped on every programmatic clipboard write.
+static SELF_WRITE_GENERATION: AtomicU64 = AtomicU64::new(0);
+static LAST_SELF_WRITE: Mutex<Option<SelfWrite>> = Mutex::new(None);
+
+struct SelfWrite {
+    generation: u64,
+    hash: u64,
+    at: Instant,
+}
+
+fn text_hash(text: &str) -> u64 {
+    xxh3_64(text.as_bytes())
+}
+
+/// Hashes every pixel, so same-size images that differ anywhere get
+/// different hashes. xxh3 keeps this cheap enough to run on each change.
+fn image_hash(width: usize, height: usize, bytes: &[u8]) -> u64 {
+    let mut hasher = Xxh3::new();
+    hasher.update(&(width as u64).to_le_bytes());
+    hasher.update(&(height as u64).to_le_bytes());
+    hasher.update(bytes);
+    hasher.digest()
+}
+
+fn mark_self_write(hash: u64) {
+    let generation = SELF_WRITE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    *LAST_SELF_WRITE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(SelfWrite {
+        generation,
+        hash,
+        at: Instant::now(),
+    });
+}
+
+/// Call before putting `text` on the clipboard so the listener doesn't
+/// record it as a new copy.
+pub fn mark_self_write_text(text: &str) {
+    mark_self_write(text_hash(text));
+}
+
+/// File-list counterpart of `mark_self_write_text`.
+pub fn mark_self_write_files(paths: &[&str]) {
+    mark_self_write(text_hash(&paths.join("\n")));
+}
+
+/// Image counterpart of `mark_self_write_text`; `bytes` is RGBA.
+pub fn mark_self_write_image(width: usize, height: usize, bytes: &[u8]) {
+    mark_self_write(image_hash(width, height, bytes));
+}
+
+#[derive(Clone)]
+struct EncodedImage {
+    hash: u64,
+    width: u32,
+    height: u32,
+    /// Full-resolution PNG.
+    png: Vec<u8>,
+    /// Downscaled PNG, when the image exceeds the preview size.
+    preview: Option<String>,
+}
+
+impl EncodedImage {
+    fn size(&self) -> usize {
+        self.png.len() + self.preview.as_ref().map_or(0, String::len)
+    }
+}
+
+// Recently encoded images, so flipping between a few screenshots doesn't
+// re-encode them on every change. Oldest first.
+const ENCODED_CACHE_MAX_ENTRIES: usize = 8;
+const ENCODED_CACHE_MAX_BYTES: usize = 32 * 1024 * 1024;
+
+static ENCODED_CACHE: Mutex<VecDeque<CachedImage>> = Mutex::new(VecDeque::new());
+
+struct CachedImage {
+    /// `Settings::image_preview_max_px` the preview was made with.
+    preview_max_px: u32,
+    encoded: EncodedImage,
+}
+
+fn cached_image(hash: u64, preview_max_px: u32) -> Option<EncodedImage> {
+    let mut cache = ENCODED_CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    let index = cache
+        .iter()
+        .position(|c| c.encoded.hash == hash && c.preview_max_px == preview_max_px)?;
+    let entry = cache.remove(index)?;
+    let encoded = entry.encoded.clone();
+    cache.push_back(entry);
+    Some(encoded)
+}
+
+fn cache_image(preview_max_px: u32, encoded: &EncodedImage) {
+    if encoded.size() > ENCODED_CACHE_MAX_BYTES {
+        return;
+    }
+    let mut cache = ENCODED_CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    cache.retain(|c| c.encoded.hash != encoded.hash);
+    cache.push_back(CachedImage {
+        preview_max_px,
+        encoded: encoded.clone(),
+    });
+    let mut total: usize = cache.iter().map(|c| c.encoded.size()).sum();
+    while cache.len() > ENCODED_CACHE_MAX_ENTRIES || total > ENCODED_CACHE_MAX_BYTES {
+        let Some(evicted) = cache.pop_front() else {
+            break;
+        };
+        total -= evicted.encoded.size();
+    }
+}
+
+fn encode_image(img: ImageData<'static>) -> Option<EncodedImage> {
+    let hash = image_hash(img.width, img.height, &img.bytes);
+    let max = settings::get().image_preview_max_px;
+    if let Some(encoded) = cached_image(hash, max) {
+        return Some(encoded);
     }
-    let hash = hasher.finish();
 
     let rgba = image::RgbaImage::from_raw(
         img.width as u32,
@@ -34,82 +384,850 @@ fn image_to_data_url(img: ImageData<'static>) -> Option<(u64, String)> {
         img.into_owned_bytes().into_owned(),
     )?;
 
+    let (width, height) = rgba.dimensions();
     let mut png_bytes = Vec::new();
     let dyn_img = image::DynamicImage::ImageRgba8(rgba);
     dyn_img
         .write_to(&mut Cursor::new(&mut png_bytes), image::ImageFormat::Png)
         .ok()?;
-    let b64 = general_purpose::STANDARD.encode(png_bytes);
-    let data_url = format!("data:image/png;base64,{b64}");
-    Some((hash, data_url))
+    let preview = if max > 0 && (width > max || height > max) {
+        let small = images::fit_within(dyn_img, max, max);
+        match images::encode(&small, images::OutputFormat::Png, None) {
+            Ok(bytes) => Some(images::to_data_url(&bytes, images::OutputFormat::Png)),
+            Err(e) => {
+                warn!("clipboard_listener: failed to encode preview: {e}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let encoded = EncodedImage {
+        hash,
+        width,
+        height,
+        png: png_bytes,
+        preview,
+    };
+    cache_image(max, &encoded);
+    Some(encoded)
 }
 
-pub fn start(app: AppHandle) {
-    thread::spawn(move || {
-        let clipboard = Clipboard::new();
-        if clipboard.is_err() {
-            eprintln!("Failed to init clipboard: {:?}", clipboard.err());
+/// Paths of files copied in Explorer (CF_HDROP). `None` if the clipboard
+/// holds no file list, and always on other platforms.
+fn read_file_list() -> Option<Vec<String>> {
+    #[cfg(target_os = "windows")]
+    unsafe {
+        use windows::Win32::Foundation::HWND;
+        use windows::Win32::System::DataExchange::{
+            CloseClipboard, GetClipboardData, IsClipboardFormatAvailable, OpenClipboard,
+        };
+        use windows::Win32::System::Ole::CF_HDROP;
+        use windows::Win32::UI::Shell::{DragQueryFileW, HDROP};
+
+        IsClipboardFormatAvailable(CF_HDROP.0 as u32).ok()?;
+        OpenClipboard(HWND::default()).ok()?;
+        let paths = GetClipboardData(CF_HDROP.0 as u32).ok().map(|handle| {
+            let hdrop = HDROP(handle.0);
+            // 0xFFFFFFFF asks for the number of files.
+            let count = DragQueryFileW(hdrop, u32::MAX, None);
+            (0..count)
+                .filter_map(|index| {
+                    let len = DragQueryFileW(hdrop, index, None) as usize;
+                    let mut buf = vec![0u16; len + 1];
+                    let copied = DragQueryFileW(hdrop, index, Some(&mut buf)) as usize;
+                    (copied > 0).then(|| String::from_utf16_lossy(&buf[..copied]))
+                })
+                .collect::<Vec<_>>()
+        });
+        let _ = CloseClipboard();
+        return paths.filter(|paths| !paths.is_empty());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    None
+}
+
+/// Whether the app that wrote the clipboard asked monitors not to record it,
+/// via the documented "ExcludeClipboardContentFromMonitorProcessing" format
+/// (password managers set it on copied secrets).
+fn excluded_from_monitoring() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        use std::sync::OnceLock;
+        use windows::core::w;
+        use windows::Win32::System::DataExchange::{
+            IsClipboardFormatAvailable, RegisterClipboardFormatW,
+        };
+
+        static FORMAT: OnceLock<u32> = OnceLock::new();
+        let format = *FORMAT.get_or_init(|| unsafe {
+            RegisterClipboardFormatW(w!("ExcludeClipboardContentFromMonitorProcessing"))
+        });
+        // 0 means registration failed; nothing can carry the marker then.
+        return format != 0 && unsafe { IsClipboardFormatAvailable(format) }.is_ok();
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    false
+}
+
+/// The "Rich Text Format" flavor of the clipboard. Always `None` on other
+/// platforms.
+fn read_rtf() -> Option<String> {
+    #[cfg(target_os = "windows")]
+    unsafe {
+        use windows::core::w;
+        use windows::Win32::Foundation::{HGLOBAL, HWND};
+        use windows::Win32::System::DataExchange::{
+            CloseClipboard, GetClipboardData, IsClipboardFormatAvailable, OpenClipboard,
+            RegisterClipboardFormatW,
+        };
+        use windows::Win32::System::Memory::{GlobalLock, GlobalSize, GlobalUnlock};
+
+        let format = RegisterClipboardFormatW(w!("Rich Text Format"));
+        if format == 0 {
+            return None;
+        }
+        IsClipboardFormatAvailable(format).ok()?;
+        OpenClipboard(HWND::default()).ok()?;
+        let rtf = GetClipboardData(format).ok().and_then(|handle| {
+            let hglobal = HGLOBAL(handle.0);
+            let ptr = GlobalLock(hglobal) as *const u8;
+            if ptr.is_null() {
+                return None;
+            }
+            let bytes = std::slice::from_raw_parts(ptr, GlobalSize(hglobal));
+            // The data is NUL-terminated inside a possibly larger block.
+            let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            let rtf = String::from_utf8_lossy(&bytes[..len]).into_owned();
+            let _ = GlobalUnlock(hglobal);
+            Some(rtf)
+        });
+        let _ = CloseClipboard();
+        return rtf.filter(|rtf| !rtf.is_empty());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    None
+}
+
+/// Windows (the sequence number) and macOS (the pasteboard's change count)
+/// bump this on every clipboard write, even when the new content is
+/// identical to the old, which is what lets us tell a re-copy from no change.
+/// `None` where no such counter is available.
+fn clipboard_sequence_number() -> Option<u32> {
+    #[cfg(target_os = "windows")]
+    {
+        // 0 means we lack access to the window station.
+        let seq = unsafe { GetClipboardSequenceNumber() };
+        return (seq != 0).then_some(seq);
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        use objc2_app_kit::NSPasteboard;
+
+        // Only compared for equality, so wrapping into u32 is fine.
+        let count = unsafe { NSPasteboard::generalPasteboard().changeCount() };
+        return Some(count as u32);
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    None
+}
+
+fn source_app() -> Option<String> {
+    #[cfg(target_os = "windows")]
+    {
+        return crate::key_listener::foreground_process_name();
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    None
+}
+
+fn is_ignored_app(app: &str) -> bool {
+    let lower = app.to_lowercase();
+    let name = lower.strip_suffix(".exe").unwrap_or(&lower);
+    settings::get()
+        .ignored_apps
+        .iter()
+        .any(|ignored| ignored.to_lowercase().trim_end_matches(".exe") == name)
+}
+
+/// Records and emits a capture. `recopy` marks content identical to the last
+/// capture that was explicitly copied again. Copies made while monitoring is
+/// paused are dropped, as are copies made in an ignored app (see
+/// `Settings::ignored_apps`) unless `Settings::ignored_app_expiry_ms` keeps
+/// them for a while.
+fn publish(app: &AppHandle, mut update: ClipboardUpdate, recopy: bool) {
+    // The watcher still tracks what it has seen while paused, so resuming
+    // doesn't replay copies made in the meantime.
+    if is_paused() {
+        return;
+    }
+    update.source_app = source_app();
+    update.timestamp_ms = history::now_ms();
+    if let Some(source) = update.source_app.as_deref().filter(|s| is_ignored_app(s)) {
+        let ttl = settings::get().ignored_app_expiry_ms;
+        if ttl == 0 {
+            debug!("clipboard_listener: ignoring copy from {source}");
             return;
         }
-        let mut clipboard = clipboard.unwrap();
-
-        let mut last_text = String::new();
-        let mut last_image_hash: u64 = 0;
-
-        // Initialize with current content to avoid re-triggering on startup?
-        // Or trigger it to populate list?
-        // Let's trigger it.
-
-        if let Ok(content) = clipboard.get_text() {
-            last_text = content.clone();
-            let _ = app.emit(
-                "clipboard-update",
-                ClipboardUpdate {
-                    item_type: "text".to_string(),
-                    content,
-                },
-            );
-        } else if let Ok(img) = clipboard.get_image() {
-            if let Some((hash, data_url)) = image_to_data_url(img) {
-                last_image_hash = hash;
-                let _ = app.emit(
-                    "clipboard-update",
-                    ClipboardUpdate {
-                        item_type: "image".to_string(),
-                        content: data_url,
-                    },
+        debug!("clipboard_listener: keeping copy from {source} for {ttl} ms");
+        update.expires_at = Some(update.timestamp_ms.saturating_add(ttl));
+    }
+    update.id = uuid::Uuid::now_v7().to_string();
+
+    let mut content = update.content.clone();
+    let mut image_file = None;
+    let mut text_file = None;
+    if let (Some(full), Some(hash)) = (update.full_text.take(), update.text_hash.clone()) {
+        match history::store_text(app, &hash, &full) {
+            Ok(()) => text_file = Some(hash),
+            Err(e) => {
+                warn!("clipboard_listener: {e}");
+                content = full;
+                update.text_hash = None;
+            }
+        }
+    }
+    if let (Some(png), Some(hash)) = (update.full_png.take(), update.image_hash.clone()) {
+        match history::store_image(app, &hash, &png) {
+            Ok(()) => image_file = Some(hash),
+            Err(e) => {
+                // Keep the original inline rather than lose it.
+                warn!("clipboard_listener: {e}");
+                content = images::to_data_url(&png, images::OutputFormat::Png);
+                update.image_hash = None;
+            }
+        }
+    }
+
+    let item = HistoryItem {
+        id: update.id.clone(),
+        item_type: update.item_type.clone(),
+        content,
+        html: update.html.clone(),
+        rtf: update.rtf.clone(),
+        preferred_format: None,
+        source_app: update.source_app.clone(),
+        original: update.original.clone(),
+        color: update.color.clone(),
+        image_file,
+        text_file,
+        timestamp: update.timestamp_ms,
+        expires_at: update.expires_at,
+        // Secrets from an ignored app stay on this machine.
+        no_sync: update.expires_at.is_some(),
+    };
+
+    let result = if recopy {
+        match settings::get().recopy_behavior {
+            RecopyBehavior::Ignore => return,
+            RecopyBehavior::MoveToTop => history::append(app, item),
+            RecopyBehavior::NewEntry => history::push(app, item),
+        }
+    } else {
+        history::append(app, item)
+    };
+    match result {
+        // A deduplicated copy keeps the id of the entry it merged into.
+        Ok((id, timestamp)) => {
+            update.id = id;
+            update.timestamp_ms = timestamp;
+        }
+        Err(e) => warn!("clipboard_listener: {e}"),
+    }
+    let _ = app.emit("clipboard-update", update);
+}
+
+/// Records an image that didn't come from the clipboard (e.g. a screen
+/// capture) in history like a copied one. Returns its data URL.
+pub fn record_image(app: &AppHandle, img: ImageData<'static>) -> Option<String> {
+    let encoded = encode_image(img)?;
+    let data_url = images::to_data_url(&encoded.png, images::OutputFormat::Png);
+    publish(app, ClipboardUpdate::image(encoded), false);
+    Some(data_url)
+}
+
+/// Reads the clipboard as the listener would, without recording it. Images
+/// and long text come back whole, since there is no stored file to fetch
+/// them from.
+pub fn read_current() -> Result<ClipboardUpdate, String> {
+    let mut clipboard = Clipboard::new().map_err(|e| format!("Failed to open clipboard: {e:?}"))?;
+
+    if let Some(content) = clipboard.get_text().ok().filter(|t| !t.is_empty()) {
+        let html = read_html(&mut clipboard);
+        let rtf = read_rtf();
+        let mut update = ClipboardUpdate::text(content, html.clone(), rtf.clone());
+        if let Some(full) = update.full_text.take() {
+            update.content = full;
+            update.truncated = false;
+            update.text_hash = None;
+            update.html = html;
+            update.rtf = rtf;
+        }
+        return Ok(update);
+    }
+    if let Some(paths) = read_file_list() {
+        return Ok(ClipboardUpdate::files(paths.join("\n")));
+    }
+    if let Some(encoded) = clipboard.get_image().ok().and_then(encode_image) {
+        return Ok(ClipboardUpdate::image(EncodedImage {
+            preview: None,
+            ..encoded
+        }));
+    }
+    Ok(ClipboardUpdate::empty())
+}
+
+/// Puts the newest history item back on the system clipboard, e.g. after a
+/// reboot cleared it. Returns whether anything was restored.
+fn restore_last_item(app: &AppHandle) -> bool {
+    let Some(item) = history::load(app)
+        .ok()
+        .and_then(|items| items.into_iter().next())
+    else {
+        return false;
+    };
+
+    let result = match item.item_type.as_str() {
+        "image" => history::full_content(app, &item)
+            .and_then(|data_url| crate::decode_image_data_url(&data_url))
+            .and_then(|(width, height, raw)| {
+                crate::try_set_clipboard_image(width as usize, height as usize, raw)
+            }),
+        "files" => crate::set_clipboard_files(&item.content.lines().collect::<Vec<_>>()),
+        _ => {
+            history::full_content(app, &item).and_then(|text| crate::try_set_clipboard_text(&text))
+        }
+    };
+
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("clipboard_listener: failed to restore last item: {e}");
+            false
+        }
+    }
+}
+
+// Some apps write the clipboard several times for one copy (text, then
+// HTML, then an image). Changes this close to the first one are treated as
+// the same copy.
+const COALESCE_WINDOW: Duration = Duration::from_millis(150);
+
+/// A change held back for `COALESCE_WINDOW` in case the writer follows up
+/// with a richer format.
+struct Pending {
+    update: ClipboardUpdate,
+    recopy: bool,
+    since: Instant,
+}
+
+/// How much of a copy a capture represents; within one burst of writes the
+/// highest wins: image, then text with HTML, then anything else.
+fn richness(update: &ClipboardUpdate) -> u8 {
+    match update.item_type.as_str() {
+        "image" => 2,
+        _ if update.html.is_some() => 1,
+        _ => 0,
+    }
+}
+
+/// Folds a change into the pending capture of the same burst. The burst
+/// only counts as a re-copy if every change in it was one: a writer that
+/// puts the same text twice (plain, then with HTML) makes the second change
+/// look like a re-copy of the first.
+fn coalesce(pending: Option<Pending>, update: ClipboardUpdate, recopy: bool) -> Pending {
+    match pending {
+        Some(pending) if richness(&pending.update) > richness(&update) => {
+            debug!("clipboard_listener: keeping richer capture from the same copy");
+            Pending {
+                recopy: pending.recopy && recopy,
+                ..pending
+            }
+        }
+        Some(pending) => Pending {
+            update,
+            recopy: pending.recopy && recopy,
+            since: pending.since,
+        },
+        None => Pending {
+            update,
+            recopy,
+            since: Instant::now(),
+        },
+    }
+}
+
+/// Last observed clipboard state, used to tell new content from re-reads.
+struct Watcher {
+    app: AppHandle,
+    clipboard: Clipboard,
+    last_text: String,
+    last_files: String,
+    last_image_hash: u64,
+    last_seq: Option<u32>,
+    /// Newest self-write generation already matched against a change.
+    self_write_seen: u64,
+    pending: Option<Pending>,
+}
+
+impl Watcher {
+    fn new(app: AppHandle) -> Option<Self> {
+        let clipboard = match Clipboard::new() {
+            Ok(clipboard) => clipboard,
+            Err(e) => {
+                let message = format!("Failed to init clipboard: {e:?}");
+                error!("{message}");
+                status::record_error(&message);
+                return None;
+            }
+        };
+        Some(Self {
+            app,
+            clipboard,
+            last_text: String::new(),
+            last_files: String::new(),
+            last_image_hash: 0,
+            last_seq: clipboard_sequence_number(),
+            self_write_seen: 0,
+            pending: None,
+        })
+    }
+
+    /// Whether content hashing to `hash` is the echo of our own most recent
+    /// write. Each write is matched at most once.
+    fn is_self_write(&mut self, hash: u64) -> bool {
+        let last = LAST_SELF_WRITE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let Some(write) = last.as_ref() else {
+            return false;
+        };
+        if write.generation <= self.self_write_seen
+            || write.hash != hash
+            || write.at.elapsed() > SELF_WRITE_WINDOW
+        {
+            return false;
+        }
+        self.self_write_seen = write.generation;
+        true
+    }
+
+    /// Picks up whatever is on the clipboard at launch, recording it unless
+    /// `Settings::capture_on_startup` is off.
+    fn seed(&mut self) {
+        let settings = settings::get();
+        // When restoring, the clipboard now holds an item that's already in
+        // history, so only seed the dedup state below instead of emitting it.
+        let restored = settings.restore_last_on_startup && restore_last_item(&self.app);
+        let emit = !restored && settings.capture_on_startup;
+        self.last_seq = clipboard_sequence_number();
+
+        if let Ok(content) = self.clipboard.get_text() {
+            self.last_text = content.clone();
+            if emit {
+                let html = read_html(&mut self.clipboard);
+                publish(
+                    &self.app,
+                    ClipboardUpdate::text(content, html, read_rtf()),
+                    false,
                 );
             }
+        } else if let Some(paths) = read_file_list() {
+            let content = paths.join("\n");
+            self.last_files = content.clone();
+            if emit {
+                publish(&self.app, ClipboardUpdate::files(content), false);
+            }
+        } else if settings.low_memory_mode {
+            // No image capture in low-memory mode.
+        } else if let Ok(img) = self.clipboard.get_image() {
+            if let Some(encoded) = encode_image(img) {
+                self.last_image_hash = encoded.hash;
+                if emit {
+                    publish(&self.app, ClipboardUpdate::image(encoded), false);
+                }
+            }
         }
+    }
 
-        loop {
-            if let Ok(content) = clipboard.get_text() {
-                if content != last_text && !content.is_empty() {
-                    last_text = content.clone();
-                    let _ = app.emit(
-                        "clipboard-update",
-                        ClipboardUpdate {
-                            item_type: "text".to_string(),
-                            content,
-                        },
-                    );
+    /// Holds `update` back until `flush`, replacing any pending capture that
+    /// is no richer. The window runs from the first change of a burst, so a
+    /// steady stream of writes can't hold captures back indefinitely.
+    fn queue(&mut self, update: ClipboardUpdate, recopy: bool) {
+        self.pending = Some(coalesce(self.pending.take(), update, recopy));
+    }
+
+    /// Time left before the pending capture is published, if any.
+    fn pending_remaining(&self) -> Option<Duration> {
+        self.pending
+            .as_ref()
+            .map(|pending| COALESCE_WINDOW.saturating_sub(pending.since.elapsed()))
+    }
+
+    /// Publishes the pending capture once its window has passed.
+    fn flush(&mut self) {
+        if self.pending_remaining() != Some(Duration::ZERO) {
+            return;
+        }
+        if let Some(pending) = self.pending.take() {
+            publish(&self.app, pending.update, pending.recopy);
+        }
+    }
+
+    /// Reads the clipboard and queues it if it changed since the last check.
+    fn check(&mut self) {
+        let seq = clipboard_sequence_number();
+        if seq.is_some() && seq == self.last_seq {
+            // Nothing was written since the last check, so skip the reads
+            // (and image decode) entirely.
+            return;
+        }
+        let seq_changed = seq.is_some();
+        self.last_seq = seq;
+
+        if excluded_from_monitoring() {
+            debug!("clipboard_listener: skipping content marked as excluded");
+            return;
+        }
+
+        if let Ok(content) = self.clipboard.get_text() {
+            if !content.is_empty() && (content != self.last_text || seq_changed) {
+                let recopy = content == self.last_text;
+                self.last_text = content.clone();
+                self.last_files.clear();
+                if self.is_self_write(text_hash(&content)) {
+                    return;
+                }
+                let html = read_html(&mut self.clipboard);
+                self.queue(ClipboardUpdate::text(content, html, read_rtf()), recopy);
+            }
+        } else if let Some(paths) = read_file_list() {
+            let content = paths.join("\n");
+            if content != self.last_files || seq_changed {
+                let recopy = content == self.last_files;
+                self.last_files = content.clone();
+                self.last_text.clear();
+                if self.is_self_write(text_hash(&content)) {
+                    return;
                 }
-            } else if let Ok(img) = clipboard.get_image() {
-                if let Some((hash, data_url)) = image_to_data_url(img) {
-                    if hash != last_image_hash {
-                        last_image_hash = hash;
-                        last_text.clear();
-                        let _ = app.emit(
-                            "clipboard-update",
-                            ClipboardUpdate {
-                                item_type: "image".to_string(),
-                                content: data_url,
-                            },
-                        );
+                self.queue(ClipboardUpdate::files(content), recopy);
+            }
+        } else if settings::get().low_memory_mode {
+            // No image capture in low-memory mode; reading and encoding
+            // images is the expensive path.
+        } else if let Ok(img) = self.clipboard.get_image() {
+            if let Some(encoded) = encode_image(img) {
+                let hash = encoded.hash;
+                if hash != self.last_image_hash || seq_changed {
+                    let recopy = hash == self.last_image_hash;
+                    self.last_image_hash = hash;
+                    self.last_text.clear();
+                    self.last_files.clear();
+                    if self.is_self_write(hash) {
+                        return;
                     }
+                    self.queue(ClipboardUpdate::image(encoded), recopy);
                 }
             }
-            thread::sleep(Duration::from_millis(500));
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod event_listener {
+    use std::cell::RefCell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tracing::warn;
+    use windows::core::{w, Error, Result};
+    use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM};
+    use windows::Win32::System::DataExchange::{
+        AddClipboardFormatListener, RemoveClipboardFormatListener,
+    };
+    use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, KillTimer,
+        PostMessageW, PostQuitMessage, RegisterClassW, SetTimer, HWND_MESSAGE, MSG,
+        WINDOW_EX_STYLE, WINDOW_STYLE, WM_CLIPBOARDUPDATE, WM_CLOSE, WM_DESTROY, WM_TIMER,
+        WNDCLASSW,
+    };
+
+    use super::Watcher;
+
+    const DEBOUNCE_TIMER_ID: usize = 1;
+    const FLUSH_TIMER_ID: usize = 2;
+    // Writers often put several formats on the clipboard in a row, and our own
+    // pastes set then restore it; settle before reading.
+    const DEBOUNCE_MS: u32 = 100;
+
+    static LISTENER_HWND: AtomicUsize = AtomicUsize::new(0);
+
+    thread_local! {
+        static WATCHER: RefCell<Option<Watcher>> = const { RefCell::new(None) };
+    }
+
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        msg: u32,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) -> LRESULT {
+        match msg {
+            WM_CLIPBOARDUPDATE => {
+                // Re-arming replaces any pending timer, so a burst of updates
+                // results in a single read.
+                SetTimer(hwnd, DEBOUNCE_TIMER_ID, DEBOUNCE_MS, None);
+                LRESULT(0)
+            }
+            WM_TIMER if w_param.0 == DEBOUNCE_TIMER_ID || w_param.0 == FLUSH_TIMER_ID => {
+                let _ = KillTimer(hwnd, w_param.0);
+                WATCHER.with(|watcher| {
+                    let mut watcher = watcher.borrow_mut();
+                    let Some(watcher) = watcher.as_mut() else {
+                        return;
+                    };
+                    if w_param.0 == DEBOUNCE_TIMER_ID {
+                        watcher.check();
+                    }
+                    watcher.flush();
+                    if let Some(remaining) = watcher.pending_remaining() {
+                        SetTimer(hwnd, FLUSH_TIMER_ID, remaining.as_millis() as u32, None);
+                    }
+                });
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                let _ = RemoveClipboardFormatListener(hwnd);
+                LISTENER_HWND.store(0, Ordering::SeqCst);
+                PostQuitMessage(0);
+                LRESULT(0)
+            }
+            _ => DefWindowProcW(hwnd, msg, w_param, l_param),
+        }
+    }
+
+    fn create_window() -> Result<HWND> {
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let class = WNDCLASSW {
+                lpfnWndProc: Some(window_proc),
+                hInstance: instance.into(),
+                lpszClassName: w!("CoppyClipboardListener"),
+                ..Default::default()
+            };
+            if RegisterClassW(&class) == 0 {
+                return Err(Error::from_win32());
+            }
+
+            let hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("CoppyClipboardListener"),
+                None,
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                None,
+                HINSTANCE::from(instance),
+                None,
+            )?;
+            if let Err(e) = AddClipboardFormatListener(hwnd) {
+                let _ = DestroyWindow(hwnd);
+                return Err(e);
+            }
+            Ok(hwnd)
+        }
+    }
+
+    /// Runs `watcher` off `WM_CLIPBOARDUPDATE` on the calling thread until
+    /// `stop` is called. Hands the watcher back if the listener window can't
+    /// be set up.
+    pub fn run(watcher: Watcher) -> std::result::Result<(), Watcher> {
+        let hwnd = match create_window() {
+            Ok(hwnd) => hwnd,
+            Err(e) => {
+                warn!("clipboard_listener: event listener unavailable, polling instead: {e:?}");
+                return Err(watcher);
+            }
+        };
+        LISTENER_HWND.store(hwnd.0 as usize, Ordering::SeqCst);
+        WATCHER.with(|slot| *slot.borrow_mut() = Some(watcher));
+
+        let mut msg = MSG::default();
+        unsafe {
+            while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                DispatchMessageW(&msg);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_running() -> bool {
+        LISTENER_HWND.load(Ordering::SeqCst) != 0
+    }
+
+    pub fn stop() {
+        let hwnd = LISTENER_HWND.load(Ordering::SeqCst);
+        if hwnd != 0 {
+            // The window has to be destroyed on its own thread.
+            unsafe {
+                let _ = PostMessageW(
+                    HWND(hwnd as *mut core::ffi::c_void),
+                    WM_CLOSE,
+                    WPARAM(0),
+                    LPARAM(0),
+                );
+            }
+        }
+    }
+}
+
+pub fn poll_interval_ms() -> u64 {
+    POLL_INTERVAL_MS.load(Ordering::Relaxed)
+}
+
+/// Sets how often the polling loop checks the clipboard, clamped to
+/// 50-5000ms. Returns the value actually applied. Has no effect while the
+/// Windows event listener is active.
+pub fn set_poll_interval_ms(ms: u64) -> u64 {
+    let ms = ms.clamp(MIN_POLL_INTERVAL_MS, MAX_POLL_INTERVAL_MS);
+    POLL_INTERVAL_MS.store(ms, Ordering::Relaxed);
+    ms
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Stops or resumes recording copies without stopping the watcher.
+pub fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::Relaxed);
+}
+
+/// Whether the watcher thread is up (it may still be paused).
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// Whether the Windows event listener, rather than polling, is watching.
+pub fn is_event_driven() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        return event_listener::is_running();
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    false
+}
+
+/// Watches the clipboard on a background thread. On Windows this reacts to
+/// `WM_CLIPBOARDUPDATE`; elsewhere it polls.
+pub fn start(app: AppHandle) {
+    thread::spawn(move || {
+        let Some(mut watcher) = Watcher::new(app) else {
+            return;
+        };
+        RUNNING.store(true, Ordering::Relaxed);
+        watcher.seed();
+
+        #[cfg(target_os = "windows")]
+        let mut watcher = match event_listener::run(watcher) {
+            Ok(()) => {
+                RUNNING.store(false, Ordering::Relaxed);
+                return;
+            }
+            Err(watcher) => watcher,
+        };
+
+        loop {
+            watcher.check();
+            watcher.flush();
+            let interval = Duration::from_millis(poll_interval_ms());
+            let wait = watcher
+                .pending_remaining()
+                .map_or(interval, |remaining| remaining.min(interval));
+            thread::sleep(wait);
         }
     });
 }
+
+/// Unregisters the Windows clipboard listener. No-op elsewhere.
+pub fn stop() {
+    #[cfg(target_os = "windows")]
+    event_listener::stop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_update(content: &str, html: Option<&str>) -> ClipboardUpdate {
+        ClipboardUpdate {
+            item_type: "text".to_string(),
+            content: content.to_string(),
+            html: html.map(str::to_string),
+            ..ClipboardUpdate::default()
+        }
+    }
+
+    #[test]
+    fn image_hash_sees_one_interior_pixel() {
+        let (width, height) = (16, 16);
+        let a = vec![0x80u8; width * height * 4];
+        let mut b = a.clone();
+        // Red channel of pixel (8, 8), far from either end of the buffer.
+        b[(8 * width + 8) * 4] ^= 1;
+
+        assert_ne!(image_hash(width, height, &a), image_hash(width, height, &b));
+        assert_eq!(
+            image_hash(width, height, &a),
+            image_hash(width, height, &a.clone())
+        );
+    }
+
+    #[test]
+    fn color_keeps_the_text_as_copied() {
+        let update = ClipboardUpdate::text("#FF0000 ".to_string(), None, None);
+
+        assert_eq!(update.item_type, "color");
+        assert_eq!(update.content, "#FF0000 ");
+        assert_eq!(update.color.as_deref(), Some("#ff0000"));
+        assert_eq!(update.original, None);
+    }
+
+    #[test]
+    fn same_text_twice_in_one_burst_is_not_a_recopy() {
+        // Plain text first, then the same text with HTML; the second change
+        // compares equal to the first and arrives flagged as a re-copy.
+        let pending = coalesce(None, text_update("hello", None), false);
+        let pending = coalesce(
+            Some(pending),
+            text_update("hello", Some("<b>hello</b>")),
+            true,
+        );
+
+        assert!(!pending.recopy);
+        assert_eq!(pending.update.html.as_deref(), Some("<b>hello</b>"));
+    }
+
+    #[test]
+    fn burst_of_recopies_stays_a_recopy() {
+        let pending = coalesce(None, text_update("hello", None), true);
+        let pending = coalesce(
+            Some(pending),
+            text_update("hello", Some("<b>hello</b>")),
+            true,
+        );
+
+        assert!(pending.recopy);
+    }
+}
@@ -0,0 +1,44 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+pub type Key = [u8; 32];
+
+pub fn random_salt() -> Vec<u8> {
+    let mut salt = vec![0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+/// Stretches a passphrase into a cipher key with Argon2id.
+pub fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, String> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Failed to derive key: {e:?}"))?;
+    Ok(key)
+}
+
+/// Encrypts with a fresh random nonce. Returns `(nonce, ciphertext)`.
+pub fn encrypt(key: &Key, plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = XChaCha20Poly1305::new(key.into())
+        .encrypt(&nonce, plaintext)
+        .map_err(|e| format!("Failed to encrypt: {e:?}"))?;
+    Ok((nonce.to_vec(), ciphertext))
+}
+
+/// Returns `None` when the key is wrong or the data was tampered with; the
+/// AEAD tag can't tell those apart.
+pub fn decrypt(key: &Key, nonce: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    if nonce.len() != NONCE_LEN {
+        return None;
+    }
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .ok()
+}
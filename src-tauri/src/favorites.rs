@@ -0,0 +1,566 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tracing::warn;
+
+use crate::{crypto, history, profiles};
+
+// Serializes read-modify-write cycles on the favorites files, which several
+// windows may trigger at once.
+static FAVORITES_LOCK: Mutex<()> = Mutex::new(());
+
+/// Key for encrypted favorites, set by `set_passphrase` and kept only in
+/// memory.
+static CIPHER: Mutex<Option<Cipher>> = Mutex::new(None);
+
+#[derive(Clone)]
+struct Cipher {
+    salt: Vec<u8>,
+    key: crypto::Key,
+}
+
+/// On-disk form of a favorites file while a passphrase is set. Binary fields
+/// are base64.
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    encrypted: bool,
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FavoriteFolder {
+    pub id: String,
+    pub name: String,
+    /// Position among folders; ties keep file order.
+    #[serde(default)]
+    pub order: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FavoriteItem {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub content: String,
+    /// User-given label, e.g. "work email signature".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub timestamp: u64,
+    pub folder_id: Option<String>,
+    /// Kept in a machine-local file instead of the (possibly synced)
+    /// favorites.json.
+    #[serde(default)]
+    pub no_sync: bool,
+    /// Position within its folder; ties keep file order.
+    #[serde(default)]
+    pub order: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct FavoritesState {
+    pub folders: Vec<FavoriteFolder>,
+    pub items: Vec<FavoriteItem>,
+}
+
+fn favorites_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(profiles::profile_dir(app)?.join("favorites.json"))
+}
+
+fn local_favorites_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(profiles::local_profile_dir(app)?.join("favorites.local.json"))
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    path.with_extension("json.bak")
+}
+
+fn current_cipher() -> Option<Cipher> {
+    CIPHER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+fn decode(field: &str) -> Result<Vec<u8>, String> {
+    general_purpose::STANDARD
+        .decode(field)
+        .map_err(|e| format!("Failed to parse favorites: {e:?}"))
+}
+
+fn read_encrypted(path: &Path) -> Option<EncryptedFile> {
+    let raw = fs::read_to_string(path).ok()?;
+    serde_json::from_str::<EncryptedFile>(&raw)
+        .ok()
+        .filter(|file| file.encrypted)
+}
+
+/// Plain files are still accepted whether or not a passphrase is set, so
+/// favorites written before encryption was turned on keep loading.
+fn parse_file(path: &Path, cipher: Option<&Cipher>) -> Result<FavoritesState, String> {
+    let raw = fs::read_to_string(path).map_err(|e| format!("Failed to read favorites: {e:?}"))?;
+    let raw = match serde_json::from_str::<EncryptedFile>(&raw) {
+        Ok(file) if file.encrypted => {
+            let cipher =
+                cipher.ok_or("Favorites are encrypted; enter the passphrase to unlock them")?;
+            if decode(&file.salt)? != cipher.salt {
+                return Err(
+                    "Favorites were encrypted with another passphrase; enter it to unlock them"
+                        .to_string(),
+                );
+            }
+            let data = crypto::decrypt(&cipher.key, &decode(&file.nonce)?, &decode(&file.data)?)
+                .ok_or("Wrong passphrase for favorites")?;
+            String::from_utf8(data).map_err(|e| format!("Failed to parse favorites: {e:?}"))?
+        }
+        _ => raw,
+    };
+    serde_json::from_str(&raw).map_err(|e| format!("Failed to parse favorites: {e:?}"))
+}
+
+/// Reads `path`, falling back to its backup if the file is unreadable.
+fn read_file(path: &Path, cipher: Option<&Cipher>) -> Result<FavoritesState, String> {
+    if !path.exists() {
+        return Ok(FavoritesState::default());
+    }
+    parse_file(path, cipher).or_else(|e| {
+        let backup = backup_path(path);
+        if !backup.exists() {
+            return Err(e);
+        }
+        warn!("favorites: {e}, loading backup {}", backup.display());
+        parse_file(&backup, cipher)
+    })
+}
+
+/// Replaces `path` via a temp file and rename, so a crash mid-write can't
+/// leave a truncated file. The previous version is kept as `.json.bak`.
+fn write_file(path: &Path, state: &FavoritesState, cipher: Option<&Cipher>) -> Result<(), String> {
+    let mut raw = serde_json::to_string(state)
+        .map_err(|e| format!("Failed to serialize favorites: {e:?}"))?;
+    if let Some(cipher) = cipher {
+        let (nonce, data) = crypto::encrypt(&cipher.key, raw.as_bytes())?;
+        raw = serde_json::to_string(&EncryptedFile {
+            encrypted: true,
+            salt: general_purpose::STANDARD.encode(&cipher.salt),
+            nonce: general_purpose::STANDARD.encode(nonce),
+            data: general_purpose::STANDARD.encode(data),
+        })
+        .map_err(|e| format!("Failed to serialize favorites: {e:?}"))?;
+    }
+
+    if path.exists() {
+        if let Err(e) = fs::copy(path, backup_path(path)) {
+            warn!("favorites: failed to back up {}: {e:?}", path.display());
+        }
+    }
+
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, raw).map_err(|e| format!("Failed to write favorites: {e:?}"))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write favorites: {e:?}"))
+}
+
+/// Synced favorites plus the local-only (`no_sync`) items.
+fn read(app: &AppHandle) -> Result<FavoritesState, String> {
+    read_with(app, current_cipher().as_ref())
+}
+
+fn read_with(app: &AppHandle, cipher: Option<&Cipher>) -> Result<FavoritesState, String> {
+    let mut state = read_file(&favorites_file_path(app)?, cipher)?;
+    let local = read_file(&local_favorites_file_path(app)?, cipher)?;
+    state.items.extend(local.items);
+    state.folders.sort_by_key(|folder| folder.order);
+    state.items.sort_by_key(|item| item.order);
+    Ok(state)
+}
+
+/// Splits `no_sync` items out of favorites.json into the local-only file.
+/// Refuses while encrypted favorites are locked: the state being written
+/// can't have come from them, and would replace them in plaintext.
+fn write(app: &AppHandle, state: FavoritesState) -> Result<(), String> {
+    let cipher = current_cipher();
+    if cipher.is_none() {
+        let paths = [favorites_file_path(app)?, local_favorites_file_path(app)?];
+        if paths.iter().any(|path| read_encrypted(path).is_some()) {
+            return Err(
+                "Favorites are locked; enter the passphrase before changing them".to_string(),
+            );
+        }
+    }
+    write_with(app, state, cipher.as_ref())
+}
+
+fn write_with(
+    app: &AppHandle,
+    state: FavoritesState,
+    cipher: Option<&Cipher>,
+) -> Result<(), String> {
+    let (local, synced): (Vec<FavoriteItem>, Vec<FavoriteItem>) =
+        state.items.into_iter().partition(|item| item.no_sync);
+    write_file(
+        &favorites_file_path(app)?,
+        &FavoritesState {
+            folders: state.folders,
+            items: synced,
+        },
+        cipher,
+    )?;
+    write_file(
+        &local_favorites_file_path(app)?,
+        &FavoritesState {
+            folders: Vec::new(),
+            items: local,
+        },
+        cipher,
+    )
+}
+
+pub fn load(app: &AppHandle) -> Result<FavoritesState, String> {
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    read(app)
+}
+
+pub fn save(app: &AppHandle, state: FavoritesState) -> Result<(), String> {
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    write(app, state)
+}
+
+/// Contents of all favorites, so history eviction can spare them.
+pub fn contents(app: &AppHandle) -> Result<HashSet<String>, String> {
+    Ok(load(app)?
+        .items
+        .into_iter()
+        .map(|item| item.content)
+        .collect())
+}
+
+/// Case-insensitive substring search over favorite names and text content,
+/// newest first. Image favorites only match by name, since their content is
+/// a data URL.
+pub fn search(
+    app: &AppHandle,
+    query: &str,
+    folder_id: Option<&str>,
+) -> Result<Vec<FavoriteItem>, String> {
+    let query = query.to_lowercase();
+    let mut matches: Vec<FavoriteItem> = load(app)?
+        .items
+        .into_iter()
+        .filter(|item| folder_id.is_none_or(|folder| item.folder_id.as_deref() == Some(folder)))
+        .filter(|item| {
+            let name_matches = item
+                .name
+                .as_ref()
+                .is_some_and(|name| name.to_lowercase().contains(&query));
+            name_matches
+                || (item.item_type != "image" && item.content.to_lowercase().contains(&query))
+        })
+        .collect();
+    matches.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    Ok(matches)
+}
+
+pub fn add(app: &AppHandle, item: FavoriteItem) -> Result<FavoritesState, String> {
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut state = read(app)?;
+    let exists = state
+        .items
+        .iter()
+        .any(|it| it.item_type == item.item_type && it.content == item.content);
+    if !exists {
+        let mut item = item;
+        item.order = state.items.iter().map(|it| it.order + 1).max().unwrap_or(0);
+        state.items.push(item);
+        write(app, state.clone())?;
+    }
+    Ok(state)
+}
+
+/// Serializes all favorites, images included as data URLs, for moving them
+/// to another machine.
+pub fn export(app: &AppHandle) -> Result<String, String> {
+    serde_json::to_string_pretty(&load(app)?)
+        .map_err(|e| format!("Failed to serialize favorites: {e:?}"))
+}
+
+/// Loads a bundle from `export`, either replacing the current favorites or,
+/// with `merge`, adding the ones whose content isn't already saved. Merged
+/// folders and items get fresh ids.
+pub fn import(app: &AppHandle, json: &str, merge: bool) -> Result<FavoritesState, String> {
+    let imported: FavoritesState =
+        serde_json::from_str(json).map_err(|e| format!("Invalid favorites bundle: {e}"))?;
+    if let Some(item) = imported.items.iter().find(|item| item.id.is_empty()) {
+        return Err(format!(
+            "Invalid favorites bundle: item without id ({})",
+            item.item_type
+        ));
+    }
+
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if !merge {
+        write(app, imported.clone())?;
+        return Ok(imported);
+    }
+
+    let mut state = read(app)?;
+    let prefix = format!("import-{}", history::now_ms());
+
+    let mut folder_ids = HashMap::new();
+    let folder_order = state.folders.iter().map(|f| f.order + 1).max().unwrap_or(0);
+    for (index, mut folder) in imported.folders.into_iter().enumerate() {
+        let id = format!("{prefix}-folder-{index}");
+        folder_ids.insert(folder.id, id.clone());
+        folder.id = id;
+        folder.order += folder_order;
+        state.folders.push(folder);
+    }
+
+    let mut existing: HashSet<(String, String)> = state
+        .items
+        .iter()
+        .map(|item| (item.item_type.clone(), item.content.clone()))
+        .collect();
+    let item_order = state.items.iter().map(|it| it.order + 1).max().unwrap_or(0);
+    for (index, mut item) in imported.items.into_iter().enumerate() {
+        if !existing.insert((item.item_type.clone(), item.content.clone())) {
+            continue;
+        }
+        item.id = format!("{prefix}-{index}");
+        // Items pointing at a folder that isn't in the bundle go unfiled.
+        item.folder_id = item
+            .folder_id
+            .and_then(|folder_id| folder_ids.get(&folder_id).cloned());
+        item.order += item_order;
+        state.items.push(item);
+    }
+
+    write(app, state.clone())?;
+    Ok(state)
+}
+
+/// Position of each id in `ordered_ids`, for the reorder commands. Ids not in
+/// the list go after the listed ones, keeping their relative order.
+fn reordered(ordered_ids: &[String], id: &str, current: u32) -> u32 {
+    match ordered_ids.iter().position(|ordered| ordered == id) {
+        Some(index) => index as u32,
+        None => ordered_ids.len() as u32 + current,
+    }
+}
+
+/// Persists a drag-and-drop arrangement of favorites, e.g. the ids of one
+/// folder's items in their new order.
+pub fn reorder_items(app: &AppHandle, ordered_ids: &[String]) -> Result<FavoritesState, String> {
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut state = read(app)?;
+    for item in &mut state.items {
+        item.order = reordered(ordered_ids, &item.id, item.order);
+    }
+    state.items.sort_by_key(|item| item.order);
+    write(app, state.clone())?;
+    Ok(state)
+}
+
+pub fn reorder_folders(app: &AppHandle, ordered_ids: &[String]) -> Result<FavoritesState, String> {
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut state = read(app)?;
+    for folder in &mut state.folders {
+        folder.order = reordered(ordered_ids, &folder.id, folder.order);
+    }
+    state.folders.sort_by_key(|folder| folder.order);
+    write(app, state.clone())?;
+    Ok(state)
+}
+
+/// Sets or, with an empty `name`, clears a favorite's label.
+pub fn rename(app: &AppHandle, id: &str, name: &str) -> Result<FavoritesState, String> {
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut state = read(app)?;
+    let item = state
+        .items
+        .iter_mut()
+        .find(|item| item.id == id)
+        .ok_or_else(|| format!("Favorite not found: {id}"))?;
+    let name = name.trim();
+    item.name = (!name.is_empty()).then(|| name.to_string());
+    write(app, state.clone())?;
+    Ok(state)
+}
+
+/// Files a favorite under `folder_id`, or at the root with `None`. The item
+/// goes after everything already there.
+pub fn move_to_folder(
+    app: &AppHandle,
+    id: &str,
+    folder_id: Option<&str>,
+) -> Result<FavoritesState, String> {
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut state = read(app)?;
+    if let Some(folder_id) = folder_id {
+        if !state.folders.iter().any(|folder| folder.id == folder_id) {
+            return Err(format!("Folder not found: {folder_id}"));
+        }
+    }
+    let order = state.items.iter().map(|it| it.order + 1).max().unwrap_or(0);
+    let item = state
+        .items
+        .iter_mut()
+        .find(|item| item.id == id)
+        .ok_or_else(|| format!("Favorite not found: {id}"))?;
+    item.folder_id = folder_id.map(str::to_string);
+    item.order = order;
+    write(app, state.clone())?;
+    Ok(state)
+}
+
+fn folder_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Folder name can't be empty".to_string());
+    }
+    Ok(name.to_string())
+}
+
+/// Adds a folder after the existing ones.
+pub fn create_folder(app: &AppHandle, name: &str) -> Result<FavoriteFolder, String> {
+    let name = folder_name(name)?;
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut state = read(app)?;
+    let folder = FavoriteFolder {
+        id: uuid::Uuid::now_v7().to_string(),
+        name,
+        order: state.folders.iter().map(|f| f.order + 1).max().unwrap_or(0),
+    };
+    state.folders.push(folder.clone());
+    write(app, state)?;
+    Ok(folder)
+}
+
+pub fn rename_folder(app: &AppHandle, id: &str, name: &str) -> Result<FavoritesState, String> {
+    let name = folder_name(name)?;
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut state = read(app)?;
+    let folder = state
+        .folders
+        .iter_mut()
+        .find(|folder| folder.id == id)
+        .ok_or_else(|| format!("Folder not found: {id}"))?;
+    folder.name = name;
+    write(app, state.clone())?;
+    Ok(state)
+}
+
+/// Removes a folder, moving its items to `reassign_to` or, with `None`, to
+/// the root.
+pub fn delete_folder(
+    app: &AppHandle,
+    id: &str,
+    reassign_to: Option<&str>,
+) -> Result<FavoritesState, String> {
+    if reassign_to == Some(id) {
+        return Err("Can't move a folder's items into the folder being deleted".to_string());
+    }
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut state = read(app)?;
+    if let Some(target) = reassign_to {
+        if !state.folders.iter().any(|folder| folder.id == target) {
+            return Err(format!("Folder not found: {target}"));
+        }
+    }
+    let before = state.folders.len();
+    state.folders.retain(|folder| folder.id != id);
+    if state.folders.len() == before {
+        return Err(format!("Folder not found: {id}"));
+    }
+    for item in &mut state.items {
+        if item.folder_id.as_deref() == Some(id) {
+            item.folder_id = reassign_to.map(str::to_string);
+        }
+    }
+    write(app, state.clone())?;
+    Ok(state)
+}
+
+pub fn remove(app: &AppHandle, id: &str) -> Result<FavoritesState, String> {
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut state = read(app)?;
+    let before = state.items.len();
+    state.items.retain(|item| item.id != id);
+    if state.items.len() == before {
+        return Err(format!("Favorite not found: {id}"));
+    }
+    write(app, state.clone())?;
+    Ok(state)
+}
+
+/// Unlocks and from then on encrypts the favorites files with `passphrase`.
+/// If they are already encrypted the passphrase must match; if they are plain
+/// they get encrypted now. An empty passphrase turns encryption off again,
+/// which only works while unlocked.
+pub fn set_passphrase(app: &AppHandle, passphrase: &str) -> Result<(), String> {
+    let _guard = FAVORITES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let paths = [favorites_file_path(app)?, local_favorites_file_path(app)?];
+    // Reuse the salt of existing encrypted files so a passphrase set on
+    // another machine unlocks the synced file here too.
+    let file_salt = paths
+        .iter()
+        .filter_map(|path| read_encrypted(path))
+        .find_map(|file| decode(&file.salt).ok());
+
+    let new_cipher = if passphrase.is_empty() {
+        None
+    } else {
+        let salt = file_salt.unwrap_or_else(crypto::random_salt);
+        let key = crypto::derive_key(passphrase, &salt)?;
+        Some(Cipher { salt, key })
+    };
+
+    // Reading with the new key doubles as the passphrase check; turning
+    // encryption off needs the old one.
+    let read_cipher = new_cipher.clone().or_else(current_cipher);
+    let state = read_with(app, read_cipher.as_ref())?;
+    write_with(app, state, new_cipher.as_ref())?;
+    if new_cipher.is_some() {
+        // Don't leave a plaintext copy behind in the backups.
+        for backup in paths.iter().map(|path| backup_path(path)) {
+            if backup.exists() && read_encrypted(&backup).is_none() {
+                if let Err(e) = fs::remove_file(&backup) {
+                    warn!("favorites: failed to remove {}: {e:?}", backup.display());
+                }
+            }
+        }
+    }
+    *CIPHER.lock().unwrap_or_else(PoisonError::into_inner) = new_cipher;
+    Ok(())
+}
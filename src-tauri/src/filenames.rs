@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+
+use time::OffsetDateTime;
+
+pub const DEFAULT_PATTERN: &str = "coppy_{timestamp}.{ext}";
+
+const TOKENS: &[&str] = &["{date}", "{time}", "{timestamp}", "{n}", "{ext}"];
+
+/// Checks that `pattern` only expands to a plain file name.
+pub fn validate(pattern: &str) -> Result<(), String> {
+    if pattern.trim().is_empty() {
+        return Err("Filename pattern is empty".to_string());
+    }
+    let mut literal = pattern.to_string();
+    for token in TOKENS {
+        literal = literal.replace(token, "");
+    }
+    if let Some(c) = literal.chars().find(|c| {
+        matches!(
+            c,
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '{' | '}'
+        )
+    }) {
+        return Err(format!(
+            "Filename pattern contains an invalid character: {c}"
+        ));
+    }
+    Ok(())
+}
+
+fn expand(pattern: &str, now: OffsetDateTime, n: u32, ext: &str) -> String {
+    let date = format!(
+        "{:04}-{:02}-{:02}",
+        now.year(),
+        now.month() as u8,
+        now.day()
+    );
+    let time = format!("{:02}-{:02}-{:02}", now.hour(), now.minute(), now.second());
+    let timestamp = (now.unix_timestamp_nanos() / 1_000_000).to_string();
+
+    let mut name = pattern
+        .replace("{date}", &date)
+        .replace("{time}", &time)
+        .replace("{timestamp}", &timestamp)
+        .replace("{n}", &n.to_string())
+        .replace("{ext}", ext);
+    if !pattern.contains("{ext}") {
+        name = format!("{name}.{ext}");
+    }
+    name
+}
+
+/// Inserts `_{suffix}` before the extension: `a.png` -> `a_1.png`.
+fn with_suffix(name: &str, suffix: u32) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{stem}_{suffix}.{ext}"),
+        _ => format!("{name}_{suffix}"),
+    }
+}
+
+/// Expands `pattern` into a path in `dir` that doesn't exist yet.
+///
+/// Tokens: `{date}` (YYYY-MM-DD), `{time}` (HH-MM-SS), `{timestamp}` (Unix
+/// millis), `{n}` (lowest number not already taken) and `{ext}`. `.{ext}` is
+/// appended when the pattern doesn't place it. Other collisions get `_1`,
+/// `_2`, ... appended.
+pub fn unique_path(dir: &Path, pattern: &str, ext: &str) -> PathBuf {
+    let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+
+    if pattern.contains("{n}") {
+        let mut n = 1;
+        loop {
+            let path = dir.join(expand(pattern, now, n, ext));
+            if !path.exists() {
+                return path;
+            }
+            n += 1;
+        }
+    }
+
+    let name = expand(pattern, now, 1, ext);
+    let mut path = dir.join(&name);
+    let mut suffix = 1;
+    while path.exists() {
+        path = dir.join(with_suffix(&name, suffix));
+        suffix += 1;
+    }
+    path
+}
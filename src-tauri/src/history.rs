@@ -0,0 +1,533 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use tracing::warn;
+
+use crate::{favorites, images, profiles, settings};
+
+const LOW_MEMORY_MAX_HISTORY_ITEMS: usize = 50;
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(15);
+// Images and long texts are stored before their entry is added, so a fresh
+// unreferenced file may just be waiting for it.
+const ORPHAN_FILE_GRACE: Duration = Duration::from_secs(60);
+
+// Serializes read-modify-write cycles on history.json between the listener
+// thread and commands.
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+/// Which flavor of a text entry a paste puts on the clipboard.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PasteFormat {
+    Text,
+    Html,
+}
+
+impl PasteFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format {
+            "text" => Ok(Self::Text),
+            "html" => Ok(Self::Html),
+            other => Err(format!("Unknown paste format: {other}")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HistoryItem {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub content: String,
+    /// HTML flavor captured alongside text copies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+    /// RTF flavor captured alongside text copies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtf: Option<String>,
+    /// Flavor pasted by default; plain text when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_format: Option<PasteFormat>,
+    /// Executable of the app the entry was copied from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_app: Option<String>,
+    /// Text as copied, when whitespace normalization changed it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original: Option<String>,
+    /// Normalized `#rrggbb` or `#rrggbbaa` of a `"color"` entry, whose
+    /// `content` stays the text as copied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Hash naming the stored full-resolution file of an image whose
+    /// `content` is only a preview; see `full_image`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_file: Option<String>,
+    /// Hash naming the stored whole of a text whose `content` was cut at
+    /// `Settings::max_text_length`; see `full_text`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_file: Option<String>,
+    pub timestamp: u64,
+    /// Unix millis after which the entry is dropped by the expiry sweep.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Kept in a machine-local file instead of the (possibly synced)
+    /// history.json.
+    #[serde(default)]
+    pub no_sync: bool,
+}
+
+fn max_items() -> usize {
+    let settings = settings::get();
+    let max = settings.max_history_items.max(1);
+    if settings.low_memory_mode {
+        max.min(LOW_MEMORY_MAX_HISTORY_ITEMS)
+    } else {
+        max
+    }
+}
+
+/// Size of an image entry, counting its full-resolution file.
+fn image_size(app: &AppHandle, item: &HistoryItem) -> u64 {
+    let file_size = item
+        .image_file
+        .as_deref()
+        .and_then(|hash| image_file_path(app, hash).ok())
+        .and_then(|path| fs::metadata(path).ok())
+        .map_or(0, |meta| meta.len());
+    item.content.len() as u64 + file_size
+}
+
+fn image_bytes(app: &AppHandle, items: &[HistoryItem]) -> u64 {
+    items
+        .iter()
+        .filter(|item| item.item_type == "image")
+        .map(|item| image_size(app, item))
+        .sum()
+}
+
+/// Drops the oldest entries until `items` (newest first) fits the item limit
+/// and the image byte budget. Entries whose content is also a favorite go
+/// last, unless favorites can't be read. Returns whether anything was
+/// dropped.
+fn evict(app: &AppHandle, items: &mut Vec<HistoryItem>) -> bool {
+    let max = max_items();
+    let budget = match settings::get().max_history_image_bytes {
+        0 => u64::MAX,
+        budget => budget,
+    };
+    let mut count = items.len();
+    let mut bytes = image_bytes(app, items);
+    if count <= max && bytes <= budget {
+        return false;
+    }
+
+    // Without the list of favorites (e.g. while they're locked) there's no
+    // telling which entries to spare. The limits still hold, so nothing is.
+    let favorites = favorites::contents(app).unwrap_or_else(|e| {
+        warn!("history: evicting without sparing favorites, {e}");
+        HashSet::new()
+    });
+
+    let mut keep = vec![true; items.len()];
+    for (index, item) in items.iter().enumerate().rev() {
+        if count <= max && bytes <= budget {
+            break;
+        }
+        if favorites.contains(&item.content) {
+            continue;
+        }
+        let is_image = item.item_type == "image";
+        if count <= max && !is_image {
+            // Only the image budget is exceeded.
+            continue;
+        }
+        keep[index] = false;
+        count -= 1;
+        if is_image {
+            bytes -= image_size(app, item);
+        }
+    }
+
+    let mut keep = keep.into_iter();
+    items.retain(|_| keep.next().unwrap_or(true));
+    // Everything left is a favorite; the item limit still wins.
+    items.truncate(max);
+    true
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn history_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(profiles::profile_dir(app)?.join("history.json"))
+}
+
+fn local_history_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(profiles::local_profile_dir(app)?.join("history.local.json"))
+}
+
+fn images_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(profiles::profile_dir(app)?.join("images"))
+}
+
+fn texts_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(profiles::profile_dir(app)?.join("texts"))
+}
+
+/// The hash ends up in a path, so don't let it be anything but hex.
+fn check_hash(hash: &str) -> Result<(), String> {
+    if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid hash: {hash}"));
+    }
+    Ok(())
+}
+
+fn image_file_path(app: &AppHandle, hash: &str) -> Result<PathBuf, String> {
+    check_hash(hash)?;
+    Ok(images_dir(app)?.join(format!("{hash}.png")))
+}
+
+fn text_file_path(app: &AppHandle, hash: &str) -> Result<PathBuf, String> {
+    check_hash(hash)?;
+    Ok(texts_dir(app)?.join(format!("{hash}.txt")))
+}
+
+/// Saves the full-resolution PNG of a previewed image entry under its pixel
+/// hash. An existing file for the hash holds the same image and is kept.
+pub fn store_image(app: &AppHandle, hash: &str, png: &[u8]) -> Result<(), String> {
+    let path = image_file_path(app, hash)?;
+    if path.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(images_dir(app)?)
+        .map_err(|e| format!("Failed to create image folder: {e:?}"))?;
+    fs::write(path, png).map_err(|e| format!("Failed to write image: {e:?}"))
+}
+
+/// Data URL of the file stored by `store_image`.
+pub fn full_image(app: &AppHandle, hash: &str) -> Result<String, String> {
+    let bytes = fs::read(image_file_path(app, hash)?)
+        .map_err(|e| format!("Failed to read image: {e:?}"))?;
+    Ok(images::to_data_url(&bytes, images::OutputFormat::Png))
+}
+
+/// Saves the whole of a truncated text entry under its hash, like
+/// `store_image`.
+pub fn store_text(app: &AppHandle, hash: &str, text: &str) -> Result<(), String> {
+    let path = text_file_path(app, hash)?;
+    if path.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(texts_dir(app)?)
+        .map_err(|e| format!("Failed to create text folder: {e:?}"))?;
+    fs::write(path, text).map_err(|e| format!("Failed to write text: {e:?}"))
+}
+
+/// The text stored by `store_text`.
+pub fn full_text(app: &AppHandle, hash: &str) -> Result<String, String> {
+    fs::read_to_string(text_file_path(app, hash)?)
+        .map_err(|e| format!("Failed to read text: {e:?}"))
+}
+
+/// `item`'s whole content: the stored original of a previewed image or a
+/// truncated text, the content itself for everything else.
+pub fn full_content(app: &AppHandle, item: &HistoryItem) -> Result<String, String> {
+    match (&item.image_file, &item.text_file) {
+        (Some(hash), _) => full_image(app, hash),
+        (None, Some(hash)) => full_text(app, hash),
+        (None, None) => Ok(item.content.clone()),
+    }
+}
+
+/// Deletes stored images and texts no entry refers to any more.
+fn prune_files(app: &AppHandle, items: &[HistoryItem]) -> Result<(), String> {
+    let images: HashSet<String> = items
+        .iter()
+        .filter_map(|item| item.image_file.as_ref())
+        .map(|hash| format!("{hash}.png"))
+        .collect();
+    prune_dir(&images_dir(app)?, &images)?;
+    let texts: HashSet<String> = items
+        .iter()
+        .filter_map(|item| item.text_file.as_ref())
+        .map(|hash| format!("{hash}.txt"))
+        .collect();
+    prune_dir(&texts_dir(app)?, &texts)
+}
+
+fn prune_dir(dir: &Path, referenced: &HashSet<String>) -> Result<(), String> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {e:?}", dir.display()))?;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let fresh = entry
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age < ORPHAN_FILE_GRACE);
+        if !referenced.contains(&name) && !fresh {
+            if let Err(e) = fs::remove_file(entry.path()) {
+                warn!("history: failed to remove {name}: {e:?}");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<Vec<HistoryItem>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let raw = fs::read_to_string(path).map_err(|e| format!("Failed to read history: {e:?}"))?;
+    serde_json::from_str(&raw).map_err(|e| format!("Failed to parse history: {e:?}"))
+}
+
+/// Replaces `path` via a temp file and rename, so a crash mid-write can't
+/// leave a truncated file.
+fn write_file(path: &Path, items: &[&HistoryItem]) -> Result<(), String> {
+    let raw =
+        serde_json::to_string(items).map_err(|e| format!("Failed to serialize history: {e:?}"))?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, raw).map_err(|e| format!("Failed to write history: {e:?}"))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write history: {e:?}"))
+}
+
+/// Reads synced and local-only entries merged into one list, newest first.
+fn read(app: &AppHandle) -> Result<Vec<HistoryItem>, String> {
+    let mut items = read_file(&history_file_path(app)?)?;
+    items.extend(read_file(&local_history_file_path(app)?)?);
+    items.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    Ok(items)
+}
+
+fn write(app: &AppHandle, items: &[HistoryItem]) -> Result<(), String> {
+    let (local, synced): (Vec<&HistoryItem>, Vec<&HistoryItem>) =
+        items.iter().partition(|item| item.no_sync);
+    write_file(&history_file_path(app)?, &synced)?;
+    write_file(&local_history_file_path(app)?, &local)?;
+    if let Err(e) = prune_files(app, items) {
+        warn!("history: {e}");
+    }
+    Ok(())
+}
+
+/// Returns the active profile's history, newest first. Entries past their
+/// expiry are hidden even if the sweep hasn't removed them yet.
+pub fn load(app: &AppHandle) -> Result<Vec<HistoryItem>, String> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let now = now_ms();
+    let mut items = read(app)?;
+    items.retain(|item| item.expires_at.is_none_or(|at| at > now));
+    Ok(items)
+}
+
+pub fn find(app: &AppHandle, id: &str) -> Result<HistoryItem, String> {
+    load(app)?
+        .into_iter()
+        .find(|item| item.id == id)
+        .ok_or_else(|| format!("History item not found: {id}"))
+}
+
+/// Adds `item` as the newest entry even if identical content already exists.
+/// Returns the id and timestamp it was stored with, like `append`.
+pub fn push(app: &AppHandle, item: HistoryItem) -> Result<(String, u64), String> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut items = read(app)?;
+
+    let stored = (item.id.clone(), item.timestamp);
+    items.insert(0, item);
+    evict(app, &mut items);
+    write(app, &items)?;
+    Ok(stored)
+}
+
+/// Adds `item` as the newest entry. If an entry with the same content is
+/// already in history, that one is moved to the top with `item`'s timestamp
+/// instead, keeping its id and flags. Returns the id and timestamp of the
+/// stored entry.
+pub fn append(app: &AppHandle, item: HistoryItem) -> Result<(String, u64), String> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut items = read(app)?;
+
+    let item = match items.iter().position(|it| {
+        it.item_type == item.item_type
+            && it.content == item.content
+            && it.text_file == item.text_file
+            && it.image_file == item.image_file
+    }) {
+        Some(index) => {
+            let mut existing = items.remove(index);
+            existing.timestamp = item.timestamp;
+            existing
+        }
+        None => item,
+    };
+
+    let stored = (item.id.clone(), item.timestamp);
+    items.insert(0, item);
+    evict(app, &mut items);
+    write(app, &items)?;
+    Ok(stored)
+}
+
+pub fn set_no_sync(app: &AppHandle, id: &str, no_sync: bool) -> Result<(), String> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut items = read(app)?;
+
+    let item = items
+        .iter_mut()
+        .find(|item| item.id == id)
+        .ok_or_else(|| format!("History item not found: {id}"))?;
+    item.no_sync = no_sync;
+
+    write(app, &items)
+}
+
+/// Trims the stored history to the current size limits.
+pub fn enforce_limit(app: &AppHandle) -> Result<(), String> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut items = read(app)?;
+    if !evict(app, &mut items) {
+        return Ok(());
+    }
+    write(app, &items)
+}
+
+/// Sets (or with `None`, clears) the flavor an entry pastes as. HTML can
+/// only be preferred by entries captured with it.
+pub fn set_preferred_format(
+    app: &AppHandle,
+    id: &str,
+    format: Option<PasteFormat>,
+) -> Result<(), String> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut items = read(app)?;
+
+    let item = items
+        .iter_mut()
+        .find(|item| item.id == id)
+        .ok_or_else(|| format!("History item not found: {id}"))?;
+    if format == Some(PasteFormat::Html) && item.html.is_none() {
+        return Err(format!("History item has no HTML to paste: {id}"));
+    }
+    item.preferred_format = format;
+
+    write(app, &items)
+}
+
+/// HTML to paste along with the text of entry `id`, when it prefers HTML.
+pub fn preferred_html(app: &AppHandle, id: &str) -> Option<String> {
+    find(app, id)
+        .ok()
+        .filter(|item| item.preferred_format == Some(PasteFormat::Html))
+        .and_then(|item| item.html)
+}
+
+/// Sets (or with `None`, clears) a per-item time-to-live.
+pub fn set_expiry(app: &AppHandle, id: &str, ttl_ms: Option<u64>) -> Result<(), String> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut items = read(app)?;
+
+    let item = items
+        .iter_mut()
+        .find(|item| item.id == id)
+        .ok_or_else(|| format!("History item not found: {id}"))?;
+    item.expires_at = ttl_ms.map(|ttl| now_ms().saturating_add(ttl));
+
+    write(app, &items)
+}
+
+/// Removes entry `id` along with its stored image or text, and emits
+/// `history-item-deleted` with the id.
+pub fn remove(app: &AppHandle, id: &str) -> Result<(), String> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut items = read(app)?;
+
+    let index = items
+        .iter()
+        .position(|item| item.id == id)
+        .ok_or_else(|| format!("History item not found: {id}"))?;
+    let removed = items.remove(index);
+    write(app, &items)?;
+
+    // `write` spares files younger than ORPHAN_FILE_GRACE, but this one is
+    // known to be unreferenced unless a re-copy shares it.
+    let shared = |hash: &String| {
+        items.iter().any(|item| {
+            item.image_file.as_ref() == Some(hash) || item.text_file.as_ref() == Some(hash)
+        })
+    };
+    let files = [
+        removed
+            .image_file
+            .filter(|hash| !shared(hash))
+            .map(|hash| image_file_path(app, &hash)),
+        removed
+            .text_file
+            .filter(|hash| !shared(hash))
+            .map(|hash| text_file_path(app, &hash)),
+    ];
+    for path in files.into_iter().flatten() {
+        let path = path?;
+        if path.exists() {
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("history: failed to remove {}: {e:?}", path.display());
+            }
+        }
+    }
+
+    let _ = app.emit("history-item-deleted", id);
+    Ok(())
+}
+
+/// Removes every entry (favorites are kept separately and stay) and emits
+/// `history-cleared`.
+pub fn clear(app: &AppHandle) -> Result<(), String> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    write(app, &[])?;
+    let _ = app.emit("history-cleared", ());
+    Ok(())
+}
+
+/// Drops expired entries and emits `item-expired` with the id of each one.
+pub fn remove_expired(app: &AppHandle) -> Result<(), String> {
+    let _guard = HISTORY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let items = read(app)?;
+
+    let now = now_ms();
+    let (expired, kept): (Vec<HistoryItem>, Vec<HistoryItem>) = items
+        .into_iter()
+        .partition(|item| item.expires_at.is_some_and(|at| at <= now));
+    if expired.is_empty() {
+        return Ok(());
+    }
+
+    write(app, &kept)?;
+    for item in expired {
+        let _ = app.emit("item-expired", item.id);
+    }
+    Ok(())
+}
+
+pub fn start_expiry_sweeper(app: AppHandle) {
+    thread::spawn(move || loop {
+        if let Err(e) = remove_expired(&app) {
+            warn!("history: expiry sweep failed: {e}");
+        }
+        thread::sleep(EXPIRY_SWEEP_INTERVAL);
+    });
+}
@@ -0,0 +1,122 @@
+use tauri::{AppHandle, Manager, PhysicalPosition};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+use tracing::warn;
+
+use crate::{settings, status};
+
+fn parse(combo: &str) -> Result<Shortcut, String> {
+    combo
+        .parse()
+        .map_err(|e| format!("Invalid shortcut {combo}: {e:?}"))
+}
+
+/// Shows the main window if it's hidden, hides it otherwise. `cursor` places
+/// it like the double-tap trigger does; see `window::place`.
+pub fn toggle_main_window(app: &AppHandle, cursor: Option<PhysicalPosition<i32>>) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    if window.is_visible().unwrap_or(false) {
+        let _ = window.hide();
+        return;
+    }
+
+    #[cfg(target_os = "windows")]
+    crate::key_listener::remember_foreground_window();
+
+    crate::window::place(&window, cursor);
+    let _ = window.show();
+    let _ = window.set_focus();
+}
+
+fn register(app: &AppHandle, shortcut: Shortcut) -> Result<(), String> {
+    app.global_shortcut()
+        .on_shortcut(shortcut, |app, _shortcut, event| {
+            if event.state == ShortcutState::Pressed {
+                toggle_main_window(app, None);
+            }
+        })
+        .map_err(|e| format!("Failed to register shortcut: {e}"))
+}
+
+fn quick_paste_shortcut(slot: usize) -> Result<Shortcut, String> {
+    parse(&format!("CmdOrCtrl+Alt+{slot}"))
+}
+
+/// Registers the shortcuts that paste history slots 1-9 straight into the
+/// focused app.
+fn register_quick_paste(app: &AppHandle) -> Result<(), String> {
+    for slot in 1..=9 {
+        app.global_shortcut()
+            .on_shortcut(quick_paste_shortcut(slot)?, move |app, _shortcut, event| {
+                // On release, so the modifiers are less likely to still be
+                // down when Ctrl+V goes out.
+                if event.state != ShortcutState::Released {
+                    return;
+                }
+                #[cfg(target_os = "windows")]
+                crate::key_listener::remember_foreground_window();
+
+                let app = app.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = crate::paste_history_index(app, slot) {
+                        warn!("hotkey: quick paste {slot}: {e}");
+                    }
+                });
+            })
+            .map_err(|e| format!("Failed to register shortcut: {e}"))?;
+    }
+    Ok(())
+}
+
+fn unregister_quick_paste(app: &AppHandle) {
+    for slot in 1..=9 {
+        if let Ok(shortcut) = quick_paste_shortcut(slot) {
+            let _ = app.global_shortcut().unregister(shortcut);
+        }
+    }
+}
+
+/// Registers the configured toggle shortcut, and the quick-paste shortcuts
+/// if enabled. Works alongside the Windows double-Ctrl hook.
+pub fn init(app: &AppHandle) {
+    let combo = settings::get().toggle_shortcut;
+    if let Err(e) = parse(&combo).and_then(|shortcut| register(app, shortcut)) {
+        warn!("hotkey: {e}");
+        status::record_error(&e);
+    }
+    if settings::get().quick_paste_shortcuts {
+        if let Err(e) = register_quick_paste(app) {
+            warn!("hotkey: {e}");
+            status::record_error(&e);
+        }
+    }
+}
+
+/// Turns the quick-paste shortcuts on or off and saves the choice.
+pub fn set_quick_paste(app: &AppHandle, enabled: bool) -> Result<(), String> {
+    unregister_quick_paste(app);
+    if enabled {
+        if let Err(e) = register_quick_paste(app) {
+            unregister_quick_paste(app);
+            return Err(e);
+        }
+    }
+    settings::update(app, |s| s.quick_paste_shortcuts = enabled)
+}
+
+/// Switches the toggle shortcut to `combo` and saves it. The old shortcut
+/// stays active if the new one can't be registered.
+pub fn set_toggle_shortcut(app: &AppHandle, combo: &str) -> Result<(), String> {
+    let shortcut = parse(combo)?;
+    let old = parse(&settings::get().toggle_shortcut).ok();
+    if old == Some(shortcut) {
+        return Ok(());
+    }
+
+    register(app, shortcut)?;
+    if let Some(old) = old {
+        let _ = app.global_shortcut().unregister(old);
+    }
+    settings::update(app, |s| s.toggle_shortcut = combo.to_string())
+}
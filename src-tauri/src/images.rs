@@ -0,0 +1,258 @@
+use std::io::Cursor;
+
+use base64::{engine::general_purpose, Engine as _};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, Luma};
+use qrcode::types::QrError;
+use qrcode::QrCode;
+
+const DEFAULT_JPEG_QUALITY: u8 = 85;
+// Small enough for the UI, large enough to scan off a monitor.
+const QR_MIN_SIZE: u32 = 256;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    WebP,
+}
+
+impl OutputFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.trim().to_ascii_lowercase().as_str() {
+            "png" => Ok(Self::Png),
+            "jpg" | "jpeg" => Ok(Self::Jpeg),
+            "webp" => Ok(Self::WebP),
+            other => Err(format!("Unsupported image format: {other}")),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+        }
+    }
+
+    /// Format named by a data URL's media type, e.g. `data:image/webp;base64`.
+    pub fn from_data_url(data_url: &str) -> Option<Self> {
+        let meta = data_url.split_once(',')?.0;
+        if meta.contains("image/png") {
+            Some(Self::Png)
+        } else if meta.contains("image/jpeg") || meta.contains("image/jpg") {
+            Some(Self::Jpeg)
+        } else if meta.contains("image/webp") {
+            Some(Self::WebP)
+        } else {
+            None
+        }
+    }
+
+    fn image_format(self) -> image::ImageFormat {
+        match self {
+            Self::Png => image::ImageFormat::Png,
+            Self::Jpeg => image::ImageFormat::Jpeg,
+            Self::WebP => image::ImageFormat::WebP,
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::WebP => "image/webp",
+        }
+    }
+}
+
+pub fn decode_data_url_bytes(data_url: &str) -> Result<Vec<u8>, String> {
+    let b64 = data_url
+        .split_once(',')
+        .map(|(_, b64)| b64)
+        .ok_or_else(|| "Invalid data URL".to_string())?;
+    general_purpose::STANDARD
+        .decode(b64)
+        .map_err(|e| format!("Failed to decode base64: {e:?}"))
+}
+
+pub fn decode_data_url(data_url: &str) -> Result<DynamicImage, String> {
+    let bytes = decode_data_url_bytes(data_url)?;
+    image::load_from_memory(&bytes).map_err(|e| format!("Failed to decode image: {e}"))
+}
+
+/// Re-encodes pixel data into `format`. `quality` (1-100) only applies to
+/// JPEG; WebP output is lossless.
+pub fn encode(
+    img: &DynamicImage,
+    format: OutputFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    match format {
+        OutputFormat::Png => img
+            .write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to encode PNG: {e:?}"))?,
+        OutputFormat::Jpeg => {
+            // JPEG has no alpha channel.
+            let quality = quality.unwrap_or(DEFAULT_JPEG_QUALITY).clamp(1, 100);
+            JpegEncoder::new_with_quality(&mut out, quality)
+                .encode_image(&img.to_rgb8())
+                .map_err(|e| format!("Failed to encode JPEG: {e:?}"))?
+        }
+        OutputFormat::WebP => DynamicImage::ImageRgba8(img.to_rgba8())
+            .write_with_encoder(WebPEncoder::new_lossless(&mut out))
+            .map_err(|e| format!("Failed to encode WebP: {e:?}"))?,
+    }
+    Ok(out)
+}
+
+/// Returns `bytes` unchanged if they already hold a `format` image, and
+/// re-encodes them into `format` otherwise.
+pub fn ensure_format(bytes: Vec<u8>, format: OutputFormat) -> Result<Vec<u8>, String> {
+    if image::guess_format(&bytes).ok() == Some(format.image_format()) {
+        return Ok(bytes);
+    }
+    let img =
+        image::load_from_memory(&bytes).map_err(|e| format!("Failed to decode image: {e}"))?;
+    encode(&img, format, None)
+}
+
+/// Re-encodes `bytes` into `format` from their pixels alone, dropping EXIF
+/// (GPS position, camera), text chunks and any other metadata the source
+/// carried.
+pub fn strip_metadata(bytes: &[u8], format: OutputFormat) -> Result<Vec<u8>, String> {
+    let img = image::load_from_memory(bytes).map_err(|e| format!("Failed to decode image: {e}"))?;
+    encode(&img, format, None)
+}
+
+/// Downscales `img` to fit within `max_width` x `max_height`, keeping its
+/// aspect ratio. Images that already fit are returned unchanged.
+pub fn fit_within(img: DynamicImage, max_width: u32, max_height: u32) -> DynamicImage {
+    if img.width() <= max_width && img.height() <= max_height {
+        return img;
+    }
+    img.resize(max_width, max_height, FilterType::Lanczos3)
+}
+
+/// Scales `img` to `max_width` x `max_height` with a Lanczos3 filter. With
+/// `keep_aspect` it fits within that box instead of being stretched to it.
+/// Neither side grows past the original unless `allow_upscale` is set.
+pub fn resize(
+    img: DynamicImage,
+    max_width: u32,
+    max_height: u32,
+    keep_aspect: bool,
+    allow_upscale: bool,
+) -> Result<DynamicImage, String> {
+    if max_width == 0 || max_height == 0 {
+        return Err(format!(
+            "Target size must be greater than 0, got {max_width}x{max_height}"
+        ));
+    }
+    match (keep_aspect, allow_upscale) {
+        (true, false) => Ok(fit_within(img, max_width, max_height)),
+        (true, true) => Ok(img.resize(max_width, max_height, FilterType::Lanczos3)),
+        (false, _) => {
+            let (width, height) = if allow_upscale {
+                (max_width, max_height)
+            } else {
+                (max_width.min(img.width()), max_height.min(img.height()))
+            };
+            if (width, height) == (img.width(), img.height()) {
+                return Ok(img);
+            }
+            Ok(img.resize_exact(width, height, FilterType::Lanczos3))
+        }
+    }
+}
+
+/// Cuts the `width` x `height` rectangle at (`x`, `y`) out of `img`. The
+/// rectangle must be non-empty and lie entirely within the image.
+pub fn crop(
+    img: &DynamicImage,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Result<DynamicImage, String> {
+    if width == 0 || height == 0 {
+        return Err(format!(
+            "Crop size must be greater than 0, got {width}x{height}"
+        ));
+    }
+    let fits =
+        |start: u32, len: u32, max: u32| start.checked_add(len).is_some_and(|end| end <= max);
+    if !fits(x, width, img.width()) || !fits(y, height, img.height()) {
+        return Err(format!(
+            "Crop rectangle {width}x{height} at ({x}, {y}) doesn't fit in the {}x{} image",
+            img.width(),
+            img.height()
+        ));
+    }
+    Ok(img.crop_imm(x, y, width, height))
+}
+
+/// Renders `text` as a black-on-white QR code with a quiet zone.
+pub fn qr_code(text: &str) -> Result<DynamicImage, String> {
+    if text.is_empty() {
+        return Err("Nothing to encode in a QR code".to_string());
+    }
+    let code = QrCode::new(text.as_bytes()).map_err(|e| match e {
+        QrError::DataTooLong => format!(
+            "Text is too long for a QR code ({} bytes, about 2300 fit)",
+            text.len()
+        ),
+        e => format!("Failed to create QR code: {e}"),
+    })?;
+    let img = code
+        .render::<Luma<u8>>()
+        .min_dimensions(QR_MIN_SIZE, QR_MIN_SIZE)
+        .build();
+    Ok(DynamicImage::ImageLuma8(img))
+}
+
+pub fn to_data_url(bytes: &[u8], format: OutputFormat) -> String {
+    let b64 = general_purpose::STANDARD.encode(bytes);
+    format!("data:{};base64,{b64}", format.mime())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A JPEG with an APP1 segment holding EXIF with a GPS tag, spliced in
+    /// right after the start-of-image marker.
+    fn jpeg_with_gps_exif() -> Vec<u8> {
+        let img = DynamicImage::new_rgb8(8, 8);
+        let plain = encode(&img, OutputFormat::Jpeg, None).unwrap();
+
+        let payload = b"Exif\0\0MM\0\x2a\0\0\0\x08GPSLatitude 52.5200 N";
+        let len = (payload.len() + 2) as u16;
+        let mut out = plain[..2].to_vec();
+        out.extend_from_slice(&[0xFF, 0xE1]);
+        out.extend_from_slice(&len.to_be_bytes());
+        out.extend_from_slice(payload);
+        out.extend_from_slice(&plain[2..]);
+        out
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn strip_metadata_drops_gps_exif() {
+        let bytes = jpeg_with_gps_exif();
+        assert!(contains(&bytes, b"Exif"));
+        assert!(image::load_from_memory(&bytes).is_ok());
+
+        let stripped = strip_metadata(&bytes, OutputFormat::Jpeg).unwrap();
+        assert!(!contains(&stripped, b"Exif"));
+        assert!(!contains(&stripped, b"GPS"));
+        assert!(!stripped.windows(2).any(|w| w == [0xFF, 0xE1]));
+        assert_eq!(image::load_from_memory(&stripped).unwrap().width(), 8);
+    }
+}
@@ -1,99 +1,417 @@
-use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicUsize, Ordering};
 use std::sync::OnceLock;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tauri::{AppHandle, Manager, PhysicalPosition};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, PhysicalPosition};
+use tracing::{error, warn};
+use crate::settings::DoubleTapKey;
 use core::ffi::c_void;
-use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM, POINT};
+use std::mem::size_of;
+use serde::Serialize;
+use windows::core::PWSTR;
+use windows::Win32::Foundation::{CloseHandle, HANDLE, HWND, LPARAM, LRESULT, WPARAM, POINT};
+use windows::Win32::Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY};
 use windows::Win32::System::LibraryLoader::GetModuleHandleA;
-use windows::Win32::UI::Input::KeyboardAndMouse::{VK_LCONTROL, VK_RCONTROL};
+use windows::Win32::System::Threading::{
+    AttachThreadInput, GetCurrentProcess, GetCurrentThreadId, OpenProcess, OpenProcessToken,
+    QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    keybd_event, KEYBD_EVENT_FLAGS, KEYEVENTF_KEYUP, VK_LCONTROL, VK_LMENU, VK_LSHIFT, VK_LWIN,
+    VK_MENU, VK_RCONTROL, VK_RMENU, VK_RSHIFT, VK_RWIN,
+};
 use windows::Win32::UI::WindowsAndMessaging::{
-    CallNextHookEx, GetCursorPos, GetForegroundWindow, GetMessageA, SetForegroundWindow,
-    SetWindowsHookExA, UnhookWindowsHookEx, KBDLLHOOKSTRUCT, MSG, WH_KEYBOARD_LL,
-    WM_KEYDOWN, WM_KEYUP, WM_SYSKEYDOWN, WM_SYSKEYUP,
+    BringWindowToTop, CallNextHookEx, GetClassNameW, GetCursorPos, GetForegroundWindow, GetMessageA, GetWindowTextW,
+    GetWindowThreadProcessId, SetForegroundWindow, SetTimer, SetWindowsHookExA, UnhookWindowsHookEx,
+    HHOOK, KBDLLHOOKSTRUCT, MSG, WH_KEYBOARD_LL, WM_KEYUP, WM_SYSKEYUP, WM_TIMER,
 };
 
+// Window classes of apps known to ignore or swallow SendInput (remote desktop
+// and VM consoles, the UAC credential prompt).
+const SYNTHETIC_INPUT_BLOCKING_CLASSES: &[&str] = &[
+    "TscShellContainerClass",
+    "VirtualConsoleClass",
+    "VMPlayerFrame",
+    "VMUIFrame",
+    "Credential Dialog Xaml Host",
+];
+
+const FOCUS_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const FOCUS_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+const FOCUS_SETTLE: Duration = Duration::from_millis(30);
+
+const HOOK_INSTALL_ATTEMPTS: u32 = 5;
+const HOOK_RETRY_DELAY: Duration = Duration::from_millis(500);
+// Windows silently removes a low-level hook whose callback once ran past
+// LowLevelHooksTimeout and doesn't say so, so the hook is periodically
+// swapped for a fresh one.
+const HOOK_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
 static LAST_CTRL_RELEASE: AtomicI64 = AtomicI64::new(0);
+// Max gap between the two Ctrl releases; 0 disables the trigger.
+static DOUBLE_CTRL_THRESHOLD_MS: AtomicI64 = AtomicI64::new(400);
+// Left/right virtual-key codes of the double-tap key.
+static DOUBLE_TAP_VK_LEFT: AtomicU32 = AtomicU32::new(VK_LCONTROL.0 as u32);
+static DOUBLE_TAP_VK_RIGHT: AtomicU32 = AtomicU32::new(VK_RCONTROL.0 as u32);
 static LAST_FOREGROUND_HWND: AtomicUsize = AtomicUsize::new(0);
 static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
+static HOOK_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_double_ctrl_threshold_ms(ms: u64) {
+    DOUBLE_CTRL_THRESHOLD_MS.store(ms.min(i64::MAX as u64) as i64, Ordering::SeqCst);
+}
+
+pub fn set_double_tap_key(key: DoubleTapKey) {
+    let (left, right) = match key {
+        DoubleTapKey::Ctrl => (VK_LCONTROL, VK_RCONTROL),
+        DoubleTapKey::Shift => (VK_LSHIFT, VK_RSHIFT),
+        DoubleTapKey::Alt => (VK_LMENU, VK_RMENU),
+        DoubleTapKey::Win => (VK_LWIN, VK_RWIN),
+    };
+    DOUBLE_TAP_VK_LEFT.store(left.0 as u32, Ordering::SeqCst);
+    DOUBLE_TAP_VK_RIGHT.store(right.0 as u32, Ordering::SeqCst);
+}
 
 pub fn focus_last_foreground_window() {
     let hwnd_val = LAST_FOREGROUND_HWND.load(Ordering::SeqCst);
-    if hwnd_val != 0 {
-        unsafe {
-            let ok = SetForegroundWindow(HWND(hwnd_val as *mut c_void)).as_bool();
-            if !ok {
-                eprintln!("focus_last_foreground_window: SetForegroundWindow failed");
+    if hwnd_val == 0 {
+        return;
+    }
+    let target = HWND(hwnd_val as *mut c_void);
+
+    unsafe {
+        // Windows refuses SetForegroundWindow from a background process
+        // unless it shares input state with the foreground, so attach to the
+        // current foreground thread and the target's while asking.
+        let current = GetCurrentThreadId();
+        let mut threads = vec![
+            GetWindowThreadProcessId(GetForegroundWindow(), None),
+            GetWindowThreadProcessId(target, None),
+        ];
+        threads.retain(|&thread| thread != 0 && thread != current);
+        threads.dedup();
+        let attached: Vec<u32> = threads
+            .into_iter()
+            .filter(|&thread| AttachThreadInput(current, thread, true).as_bool())
+            .collect();
+
+        if attached.is_empty() {
+            // Fallback: a synthetic Alt tap counts as our last input, which
+            // also lifts the foreground lock.
+            keybd_event(VK_MENU.0 as u8, 0, KEYBD_EVENT_FLAGS(0), 0);
+            keybd_event(VK_MENU.0 as u8, 0, KEYEVENTF_KEYUP, 0);
+        }
+
+        let ok = SetForegroundWindow(target).as_bool();
+        let _ = BringWindowToTop(target);
+
+        for thread in attached {
+            let _ = AttachThreadInput(current, thread, false);
+        }
+        if !ok {
+            warn!("focus_last_foreground_window: SetForegroundWindow failed");
+        }
+    }
+}
+
+/// Focuses the paste target and waits until Windows reports it as the
+/// foreground window, asking again every `FOCUS_RETRY_INTERVAL`. Fails after
+/// `timeout` so Ctrl+V doesn't go to some other window.
+pub fn focus_paste_target(timeout: Duration) -> Result<(), String> {
+    let target = LAST_FOREGROUND_HWND.load(Ordering::SeqCst);
+    if target == 0 {
+        // No target recorded; the paste goes wherever focus is.
+        return Ok(());
+    }
+
+    let started = Instant::now();
+    let mut last_attempt = started;
+    focus_last_foreground_window();
+    loop {
+        if unsafe { GetForegroundWindow() }.0 as usize == target {
+            // Let the target finish processing its activation.
+            thread::sleep(FOCUS_SETTLE);
+            return Ok(());
+        }
+        if started.elapsed() >= timeout {
+            return Err(format!("Paste target didn't regain focus within {timeout:?}"));
+        }
+        if last_attempt.elapsed() >= FOCUS_RETRY_INTERVAL {
+            focus_last_foreground_window();
+            last_attempt = Instant::now();
+        }
+        thread::sleep(FOCUS_POLL_INTERVAL);
+    }
+}
+
+/// Records the currently focused window as the paste target, for triggers
+/// other than the double-Ctrl hook.
+pub fn remember_foreground_window() {
+    let fg = unsafe { GetForegroundWindow() };
+    LAST_FOREGROUND_HWND.store(fg.0 as usize, Ordering::SeqCst);
+}
+
+pub fn last_foreground_hwnd() -> usize {
+    LAST_FOREGROUND_HWND.load(Ordering::SeqCst)
+}
+
+/// Title of the window that was focused before Coppy was summoned.
+pub fn last_foreground_window_title() -> Option<String> {
+    let hwnd_val = last_foreground_hwnd();
+    if hwnd_val == 0 {
+        return None;
+    }
+    let mut buf = [0u16; 512];
+    let len = unsafe { GetWindowTextW(HWND(hwnd_val as *mut c_void), &mut buf) };
+    if len <= 0 {
+        return None;
+    }
+    Some(String::from_utf16_lossy(&buf[..len as usize]))
+}
+
+fn window_class_name(hwnd: HWND) -> Option<String> {
+    let mut buf = [0u16; 256];
+    let len = unsafe { GetClassNameW(hwnd, &mut buf) };
+    if len <= 0 {
+        return None;
+    }
+    Some(String::from_utf16_lossy(&buf[..len as usize]))
+}
+
+/// Whether the process token says it runs elevated. `None` if the token
+/// can't be queried.
+fn process_is_elevated(process: HANDLE) -> Option<bool> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(process, TOKEN_QUERY, &mut token).ok()?;
+
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut len = 0u32;
+        let result = GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut TOKEN_ELEVATION as *mut c_void),
+            size_of::<TOKEN_ELEVATION>() as u32,
+            &mut len,
+        );
+        let _ = CloseHandle(token);
+        result.ok()?;
+        Some(elevation.TokenIsElevated != 0)
+    }
+}
+
+/// Executable name (e.g. "notepad.exe") and elevation of the process that
+/// owns `hwnd`.
+fn window_process_info(hwnd: HWND) -> (Option<String>, Option<bool>) {
+    unsafe {
+        let mut pid = 0u32;
+        GetWindowThreadProcessId(hwnd, Some(&mut pid as *mut u32));
+        if pid == 0 {
+            return (None, None);
+        }
+        let Ok(process) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) else {
+            return (None, None);
+        };
+
+        let mut buf = [0u16; 1024];
+        let mut len = buf.len() as u32;
+        let name = QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buf.as_mut_ptr()),
+            &mut len,
+        )
+        .ok()
+        .map(|_| String::from_utf16_lossy(&buf[..len as usize]))
+        .and_then(|path| {
+            std::path::Path::new(&path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        });
+        let elevated = process_is_elevated(process);
+
+        let _ = CloseHandle(process);
+        (name, elevated)
+    }
+}
+
+/// Executable name of the app in the foreground right now, e.g. the one a
+/// copy just came from.
+pub fn foreground_process_name() -> Option<String> {
+    let hwnd = unsafe { GetForegroundWindow() };
+    if hwnd.0.is_null() {
+        return None;
+    }
+    window_process_info(hwnd).0
+}
+
+#[derive(Serialize)]
+pub struct PasteTargetAssessment {
+    pub hwnd: usize,
+    pub title: Option<String>,
+    pub process: Option<String>,
+    pub elevated: bool,
+    pub likely_blocks_synthetic: bool,
+}
+
+/// Inspects the window a paste would go to for known reasons synthetic
+/// Ctrl+V gets dropped.
+pub fn assess_paste_target() -> Result<PasteTargetAssessment, String> {
+    let hwnd_val = last_foreground_hwnd();
+    if hwnd_val == 0 {
+        return Err("No paste target window recorded yet".to_string());
+    }
+    let hwnd = HWND(hwnd_val as *mut c_void);
+
+    let (process, elevated) = window_process_info(hwnd);
+    // An elevated process won't open its token to us, so an unreadable token
+    // almost always means elevated.
+    let elevated = elevated.unwrap_or(true);
+    let self_elevated = unsafe { process_is_elevated(GetCurrentProcess()) }.unwrap_or(false);
+
+    // UIPI drops input sent from a lower integrity level.
+    let blocked_by_uipi = elevated && !self_elevated;
+    let blocking_class = window_class_name(hwnd)
+        .is_some_and(|class| SYNTHETIC_INPUT_BLOCKING_CLASSES.contains(&class.as_str()));
+
+    Ok(PasteTargetAssessment {
+        hwnd: hwnd_val,
+        title: last_foreground_window_title(),
+        process,
+        elevated,
+        likely_blocks_synthetic: blocked_by_uipi || blocking_class,
+    })
+}
+
+/// Whether the double-tap keyboard hook is currently installed.
+pub fn is_hook_installed() -> bool {
+    HOOK_INSTALLED.load(Ordering::SeqCst)
+}
+
+fn install_hook() -> Result<HHOOK, String> {
+    unsafe {
+        let instance = GetModuleHandleA(None)
+            .map_err(|e| format!("Failed to get module handle: {e:?}"))?;
+        SetWindowsHookExA(WH_KEYBOARD_LL, Some(hook_callback), instance, 0)
+            .map_err(|e| format!("Failed to set keyboard hook: {e:?}"))
+    }
+}
+
+/// Tries `install_hook` up to `HOOK_INSTALL_ATTEMPTS` times, doubling the
+/// wait after each failure.
+fn install_hook_with_retry() -> Result<HHOOK, String> {
+    let mut delay = HOOK_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match install_hook() {
+            Ok(hook) => return Ok(hook),
+            Err(e) if attempt >= HOOK_INSTALL_ATTEMPTS => {
+                return Err(format!("{e} (gave up after {attempt} attempts)"));
+            }
+            Err(e) => {
+                warn!("{e}, retrying in {delay:?}");
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
             }
         }
     }
 }
 
+/// Installs the keyboard hook on its own thread. If it can't be installed,
+/// a "keyboard-hook-failed" event carries the error to the UI.
 pub fn start_listening(app: AppHandle) {
-    let _ = APP_HANDLE.set(app);
+    let _ = APP_HANDLE.set(app.clone());
 
-    std::thread::spawn(|| unsafe {
-        let instance = GetModuleHandleA(None).unwrap();
-        let hook = SetWindowsHookExA(WH_KEYBOARD_LL, Some(hook_callback), instance, 0);
+    thread::spawn(move || {
+        let mut hook = match install_hook_with_retry() {
+            Ok(hook) => hook,
+            Err(e) => {
+                error!("{e}");
+                crate::status::record_error(&e);
+                let _ = app.emit("keyboard-hook-failed", e);
+                return;
+            }
+        };
+        HOOK_INSTALLED.store(true, Ordering::SeqCst);
 
-        if hook.is_err() {
-            eprintln!("Failed to set keyboard hook");
-            return;
-        }
-        let hook = hook.unwrap();
+        unsafe {
+            // A thread timer (no window) posts WM_TIMER to this loop.
+            SetTimer(None, 0, HOOK_REFRESH_INTERVAL.as_millis() as u32, None);
+
+            let mut msg = MSG::default();
+            while GetMessageA(&mut msg, None, 0, 0).into() {
+                if msg.message != WM_TIMER {
+                    continue;
+                }
+                // Hook the new one first so no keystroke slips through.
+                match install_hook() {
+                    Ok(fresh) => {
+                        let _ = UnhookWindowsHookEx(hook);
+                        hook = fresh;
+                    }
+                    Err(e) => warn!("keyboard hook refresh: {e}"),
+                }
+            }
 
-        let mut msg = MSG::default();
-        while GetMessageA(&mut msg, None, 0, 0).into() {
-            // Processing loop
+            let _ = UnhookWindowsHookEx(hook);
         }
-        
-        let _ = UnhookWindowsHookEx(hook);
+        HOOK_INSTALLED.store(false, Ordering::SeqCst);
     });
 }
 
 unsafe extern "system" fn hook_callback(code: i32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
-    if code >= 0 {
-        let vk_code = (*(l_param.0 as *const KBDLLHOOKSTRUCT)).vkCode;
-        let event = w_param.0 as u32;
-
-        if vk_code == VK_LCONTROL.0 as u32 || vk_code == VK_RCONTROL.0 as u32 {
-             let flags = (*(l_param.0 as *const KBDLLHOOKSTRUCT)).flags.0;
-             let is_up = (flags >> 7) & 1 == 1;
-             
-             if !is_up && (event == WM_KEYDOWN || event == WM_SYSKEYDOWN) {
-                 // Key Down
-             } else if is_up && (event == WM_KEYUP || event == WM_SYSKEYUP) {
-                 // Key Up
-                 let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as i64;
-                 
-                 let last = LAST_CTRL_RELEASE.load(Ordering::SeqCst);
-                 
-                 if (now - last) < 400 {
-                     // Double click detected!
-                     if let Some(app) = APP_HANDLE.get() {
-                         if let Some(window) = app.get_webview_window("main") {
-                             if window.is_visible().unwrap_or(false) {
-                                 let _ = window.hide();
-                             } else {
-                                 let fg = GetForegroundWindow();
-                                 LAST_FOREGROUND_HWND.store(fg.0 as usize, Ordering::SeqCst);
-                                 let mut point = POINT::default();
-                                 let _ = GetCursorPos(&mut point);
-                                 // Position window above cursor (window height is 320)
-                                 let _ = window.set_position(PhysicalPosition::new(point.x, point.y - 320));
-                                 let _ = window.show();
-                                 let _ = window.set_focus();
-                             }
-                         }
-                     }
-                     LAST_CTRL_RELEASE.store(0, Ordering::SeqCst); // Reset
-                 } else {
-                     LAST_CTRL_RELEASE.store(now, Ordering::SeqCst);
-                 }
-             }
+    // Negative codes must be passed on untouched, and only non-negative ones
+    // carry a KBDLLHOOKSTRUCT.
+    if code >= 0 && l_param.0 != 0 {
+        let event = *(l_param.0 as *const KBDLLHOOKSTRUCT);
+        // Unwinding across the FFI boundary is undefined behavior, so a panic
+        // in the handler is caught and the key passed on as if unhandled.
+        if std::panic::catch_unwind(|| on_key_event(w_param.0 as u32, &event)).is_err() {
+            error!("keyboard hook: key handler panicked");
         }
     }
     CallNextHookEx(None, code, w_param, l_param)
 }
+
+fn on_key_event(event: u32, key: &KBDLLHOOKSTRUCT) {
+    let threshold = DOUBLE_CTRL_THRESHOLD_MS.load(Ordering::SeqCst);
+    let is_tap_key = key.vkCode == DOUBLE_TAP_VK_LEFT.load(Ordering::SeqCst)
+        || key.vkCode == DOUBLE_TAP_VK_RIGHT.load(Ordering::SeqCst);
+    if threshold <= 0 || !is_tap_key {
+        return;
+    }
+
+    let is_up = (key.flags.0 >> 7) & 1 == 1;
+    if !is_up || !(event == WM_KEYUP || event == WM_SYSKEYUP) {
+        return;
+    }
+
+    let Ok(since_epoch) = SystemTime::now().duration_since(UNIX_EPOCH) else {
+        return;
+    };
+    let now = since_epoch.as_millis() as i64;
+    let last = LAST_CTRL_RELEASE.load(Ordering::SeqCst);
+
+    if (now - last) < threshold {
+        // Double tap detected
+        if let Some(app) = APP_HANDLE.get() {
+            if let Some(window) = app.get_webview_window("main") {
+                if window.is_visible().unwrap_or(false) {
+                    let _ = window.hide();
+                } else {
+                    remember_foreground_window();
+                    let mut point = POINT::default();
+                    let _ = unsafe { GetCursorPos(&mut point) };
+                    // Restore the saved size; open above the cursor unless pinned
+                    crate::window::place(&window, Some(PhysicalPosition::new(point.x, point.y)));
+                    let _ = window.show();
+                    let _ = window.set_focus();
+                }
+            }
+        }
+        LAST_CTRL_RELEASE.store(0, Ordering::SeqCst); // Reset
+    } else {
+        LAST_CTRL_RELEASE.store(now, Ordering::SeqCst);
+    }
+}
@@ -5,14 +5,14 @@ use arboard::Clipboard;
 use arboard::ImageData;
 use base64::{engine::general_purpose, Engine as _};
 use enigo::{Enigo, Key, Keyboard, Settings};
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 #[cfg(not(target_os = "windows"))]
 use std::borrow::Cow;
 use std::fs;
-use std::io::Cursor;
 use std::mem::size_of;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Mutex, PoisonError};
 use tauri::Manager;
+use tracing::{debug, info, warn};
 use windows::Win32::UI::Input::KeyboardAndMouse::{
     SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS, KEYEVENTF_KEYUP,
     VIRTUAL_KEY,
@@ -38,37 +38,6 @@ use windows::Win32::{
     },
 };
 
-#[derive(Serialize, Deserialize, Clone)]
-struct FavoriteFolder {
-    id: String,
-    name: String,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-struct FavoriteItem {
-    id: String,
-    #[serde(rename = "type")]
-    item_type: String,
-    content: String,
-    timestamp: u64,
-    folder_id: Option<String>,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-struct FavoritesState {
-    folders: Vec<FavoriteFolder>,
-    items: Vec<FavoriteItem>,
-}
-
-fn favorites_file_path(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
-    let dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data dir: {e:?}"))?;
-    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data dir: {e:?}"))?;
-    Ok(dir.join("favorites.json"))
-}
-
 #[cfg(target_os = "windows")]
 fn send_ctrl_v() -> Result<(), String> {
     let ctrl = VIRTUAL_KEY(0x11);
@@ -133,55 +102,120 @@ fn send_ctrl_v() -> Result<(), String> {
     }
 }
 
+// Paste is Cmd+V on macOS and Ctrl+V everywhere else.
+#[cfg(target_os = "macos")]
+const PASTE_MODIFIER: Key = Key::Meta;
+#[cfg(not(target_os = "macos"))]
+const PASTE_MODIFIER: Key = Key::Control;
+
 #[tauri::command]
 fn simulate_paste() {
     // Wait for window to fully hide and focus to return to previous app
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     let mut enigo = Enigo::new(&Settings::default()).unwrap();
-    // Simulate Ctrl+V
-    let _ = enigo.key(Key::Control, enigo::Direction::Press);
+    // Simulate Ctrl+V (Cmd+V on macOS)
+    let _ = enigo.key(PASTE_MODIFIER, enigo::Direction::Press);
     let _ = enigo.key(Key::Unicode('v'), enigo::Direction::Click);
-    let _ = enigo.key(Key::Control, enigo::Direction::Release);
+    let _ = enigo.key(PASTE_MODIFIER, enigo::Direction::Release);
+}
+
+/// Opens and reads the clipboard once at startup, so the system clipboard
+/// libraries are loaded before the first paste. COM is left alone: it's
+/// per-thread, so the code that needs it initializes it on its own thread.
+/// The logged time is this read's, not the first paste's.
+#[tauri::command]
+fn warmup() {
+    let started = std::time::Instant::now();
+
+    match Clipboard::new() {
+        Ok(mut clipboard) => {
+            let _ = clipboard.get_text();
+        }
+        Err(e) => warn!("warmup: failed to init clipboard: {e:?}"),
+    }
+
+    info!("warmup: clipboard read in {:?}", started.elapsed());
 }
 
 fn try_set_clipboard_text(text: &str) -> Result<(), String> {
     let mut clipboard = Clipboard::new().map_err(|e| format!("Failed to init clipboard: {e:?}"))?;
+    clipboard_listener::mark_self_write_text(text);
 
-    let mut last_err: Option<String> = None;
-    for _ in 0..8 {
-        match clipboard.set_text(text.to_string()) {
-            Ok(_) => {
-                last_err = None;
-                break;
-            }
+    with_clipboard_retry("set_clipboard_text", || {
+        clipboard
+            .set_text(text.to_string())
+            .map_err(|e| format!("Failed to set clipboard text: {e:?}"))
+    })
+}
+
+// Upper bound for the doubling wait between clipboard write attempts.
+const MAX_CLIPBOARD_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Runs a clipboard write until it succeeds, up to
+/// `Settings::clipboard_retry_attempts` times, doubling the wait between
+/// tries from `Settings::clipboard_retry_delay_ms`. The usual cause of
+/// failures is another clipboard manager holding the clipboard open.
+fn with_clipboard_retry<T>(
+    operation: &str,
+    mut attempt: impl FnMut() -> Result<T, String>,
+) -> Result<T, String> {
+    let settings = settings::get();
+    let attempts = settings.clipboard_retry_attempts.max(1);
+    let mut delay = std::time::Duration::from_millis(settings.clipboard_retry_delay_ms);
+
+    let mut last_err = String::new();
+    for n in 1..=attempts {
+        match attempt() {
+            Ok(value) => return Ok(value),
             Err(e) => {
-                last_err = Some(format!("Failed to set clipboard text: {e:?}"));
-                std::thread::sleep(std::time::Duration::from_millis(40));
+                debug!("{operation}: attempt {n} failed: {e}");
+                last_err = e;
             }
         }
+        if n < attempts {
+            std::thread::sleep(delay);
+            delay = (delay * 2).min(MAX_CLIPBOARD_RETRY_DELAY);
+        }
     }
+    Err(format!(
+        "{operation} failed after {attempts} attempts: {last_err}"
+    ))
+}
 
-    if let Some(err) = last_err {
-        Err(err)
-    } else {
-        Ok(())
-    }
+#[derive(Serialize, Clone)]
+struct ClipboardError<'a> {
+    operation: &'a str,
+    message: &'a str,
+}
+
+/// Tells the UI a clipboard write gave up after its retries, e.g. because
+/// another app kept the clipboard open.
+fn emit_clipboard_error(app: &tauri::AppHandle, operation: &str, message: &str) {
+    use tauri::Emitter;
+
+    status::record_error(&format!("{operation}: {message}"));
+    let _ = app.emit("clipboard-error", ClipboardError { operation, message });
 }
 
 #[cfg(target_os = "windows")]
-fn try_set_clipboard_image(width: usize, height: usize, bytes: Vec<u8>) -> Result<(), String> {
-    use std::ptr;
-    use windows::Win32::Foundation::HWND;
-    use windows::Win32::System::DataExchange::{
-        CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData,
-    };
-    use windows::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+fn try_clear_clipboard() -> Result<(), String> {
+    replace_clipboard("clear_clipboard", |_| Ok(()))
+}
 
-    const CF_DIB: u32 = 8;
+#[cfg(not(target_os = "windows"))]
+fn try_clear_clipboard() -> Result<(), String> {
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.clear())
+        .map_err(|e| format!("Failed to clear clipboard: {e:?}"))
+}
 
+/// CF_DIB payload for tightly packed RGBA pixels: a BITMAPINFOHEADER
+/// followed by bottom-up BGRA rows.
+#[cfg(target_os = "windows")]
+fn dib_buffer(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
     // RGBA to BGRA conversion (Windows DIB uses BGRA)
-    let mut bgra = bytes.clone();
+    let mut bgra = rgba.to_vec();
     for chunk in bgra.chunks_exact_mut(4) {
         chunk.swap(0, 2); // Swap R and B
     }
@@ -214,143 +248,46 @@ fn try_set_clipboard_image(width: usize, height: usize, bytes: Vec<u8>) -> Resul
     dib_data.extend_from_slice(&0u32.to_le_bytes()); // biClrUsed
     dib_data.extend_from_slice(&0u32.to_le_bytes()); // biClrImportant
     dib_data.extend_from_slice(&flipped); // Pixel data
+    dib_data
+}
+
+#[cfg(target_os = "windows")]
+fn try_set_clipboard_image(width: usize, height: usize, bytes: Vec<u8>) -> Result<(), String> {
+    use windows::Win32::System::Ole::CF_DIB;
+
+    clipboard_listener::mark_self_write_image(width, height, &bytes);
+
+    let dib_data = dib_buffer(width, height, &bytes);
 
-    eprintln!(
+    debug!(
         "try_set_clipboard_image: width={}, height={}, dib_size={}",
         width,
         height,
         dib_data.len()
     );
 
-    let mut last_err: Option<String> = None;
-
-    for attempt in 0..8 {
-        unsafe {
-            eprintln!("try_set_clipboard_image: attempt {}", attempt);
-
-            // Open clipboard
-            if let Err(e) = OpenClipboard(HWND::default()) {
-                eprintln!("try_set_clipboard_image: OpenClipboard failed: {:?}", e);
-                last_err = Some(format!("Failed to open clipboard: {:?}", e));
-                std::thread::sleep(std::time::Duration::from_millis(40));
-                continue;
-            }
-            eprintln!("try_set_clipboard_image: OpenClipboard succeeded");
-
-            // Empty clipboard
-            if let Err(e) = EmptyClipboard() {
-                eprintln!("try_set_clipboard_image: EmptyClipboard failed: {:?}", e);
-                let _ = CloseClipboard();
-                last_err = Some(format!("Failed to empty clipboard: {:?}", e));
-                std::thread::sleep(std::time::Duration::from_millis(40));
-                continue;
-            }
-            eprintln!("try_set_clipboard_image: EmptyClipboard succeeded");
-
-            // Allocate global memory
-            let hmem = match GlobalAlloc(GMEM_MOVEABLE, dib_data.len()) {
-                Ok(h) => {
-                    eprintln!(
-                        "try_set_clipboard_image: GlobalAlloc succeeded, handle={:?}",
-                        h
-                    );
-                    h
-                }
-                Err(e) => {
-                    eprintln!("try_set_clipboard_image: GlobalAlloc failed: {:?}", e);
-                    let _ = CloseClipboard();
-                    last_err = Some(format!("Failed to allocate global memory: {e:?}"));
-                    std::thread::sleep(std::time::Duration::from_millis(40));
-                    continue;
-                }
-            };
-
-            // Lock memory and copy data
-            let pmem = GlobalLock(hmem);
-            if pmem.is_null() {
-                eprintln!("try_set_clipboard_image: GlobalLock returned null");
-                let _ = CloseClipboard();
-                last_err = Some("Failed to lock global memory".to_string());
-                std::thread::sleep(std::time::Duration::from_millis(40));
-                continue;
-            }
-            eprintln!("try_set_clipboard_image: GlobalLock succeeded");
-
-            ptr::copy_nonoverlapping(dib_data.as_ptr(), pmem as *mut u8, dib_data.len());
-            let _ = GlobalUnlock(hmem);
-            eprintln!("try_set_clipboard_image: Data copied and unlocked");
-
-            // Set clipboard data - use raw handle value
-            let handle = windows::Win32::Foundation::HANDLE(hmem.0);
-            eprintln!(
-                "try_set_clipboard_image: Calling SetClipboardData with CF_DIB={}, handle={:?}",
-                CF_DIB, handle
-            );
-            let result = SetClipboardData(CF_DIB, handle);
-
-            if let Err(e) = &result {
-                eprintln!("try_set_clipboard_image: SetClipboardData failed: {:?}", e);
-                let _ = CloseClipboard();
-                last_err = Some(format!("Failed to set clipboard data: {:?}", e));
-                std::thread::sleep(std::time::Duration::from_millis(40));
-                continue;
-            }
-            eprintln!(
-                "try_set_clipboard_image: SetClipboardData succeeded: {:?}",
-                result
-            );
-
-            let _ = CloseClipboard();
-            eprintln!("try_set_clipboard_image: CloseClipboard done, SUCCESS!");
-
-            // Success
-            last_err = None;
-            break;
-        }
-    }
-
-    if let Some(ref err) = last_err {
-        eprintln!("try_set_clipboard_image: FAILED with error: {}", err);
-        Err(err.clone())
-    } else {
-        eprintln!("try_set_clipboard_image: completed successfully");
-        Ok(())
+    let result = with_clipboard_data(CF_DIB.0 as u32, &dib_data);
+    match &result {
+        Ok(()) => debug!("try_set_clipboard_image: completed successfully"),
+        Err(err) => warn!("try_set_clipboard_image: {err}"),
     }
+    result
 }
 
 #[cfg(not(target_os = "windows"))]
 fn try_set_clipboard_image(width: usize, height: usize, bytes: Vec<u8>) -> Result<(), String> {
-    let mut last_err: Option<String> = None;
-    for _ in 0..8 {
-        let mut clipboard = match Clipboard::new() {
-            Ok(c) => c,
-            Err(e) => {
-                last_err = Some(format!("Failed to init clipboard: {e:?}"));
-                std::thread::sleep(std::time::Duration::from_millis(40));
-                continue;
-            }
-        };
-        match clipboard.set_image(ImageData {
-            width,
-            height,
-            bytes: Cow::Borrowed(&bytes),
-        }) {
-            Ok(_) => {
-                last_err = None;
-                break;
-            }
-            Err(e) => {
-                last_err = Some(format!("Failed to set clipboard image: {e:?}"));
-                std::thread::sleep(std::time::Duration::from_millis(40));
-            }
-        }
-    }
-
-    if let Some(err) = last_err {
-        Err(err)
-    } else {
-        Ok(())
-    }
+    clipboard_listener::mark_self_write_image(width, height, &bytes);
+
+    with_clipboard_retry("set_clipboard_image", || {
+        Clipboard::new()
+            .map_err(|e| format!("Failed to init clipboard: {e:?}"))?
+            .set_image(ImageData {
+                width,
+                height,
+                bytes: Cow::Borrowed(&bytes),
+            })
+            .map_err(|e| format!("Failed to set clipboard image: {e:?}"))
+    })
 }
 
 #[cfg(target_os = "windows")]
@@ -411,11 +348,9 @@ fn try_get_explorer_folder_from_hwnd(hwnd: usize) -> Option<std::path::PathBuf>
     }
 }
 
-fn save_bytes_to_default_dir(
-    app: &tauri::AppHandle,
-    bytes: &[u8],
-    extension: &str,
-) -> Result<String, String> {
+/// `Coppy` folder under the last active Explorer folder (Windows only),
+/// falling back to Downloads.
+fn default_output_dir(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
     // Prefer last active Explorer folder (Windows only). Otherwise fall back to Downloads/Coppy.
     #[cfg(target_os = "windows")]
     let base_dir = {
@@ -444,60 +379,348 @@ fn save_bytes_to_default_dir(
         .or_else(|_| app.path().app_data_dir())
         .map_err(|e| format!("Failed to get output dir: {e:?}"))?;
 
-    let out_dir = base_dir.join("Coppy");
-    fs::create_dir_all(&out_dir).map_err(|e| format!("Failed to create output dir: {e:?}"))?;
+    Ok(base_dir.join("Coppy"))
+}
 
-    let ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| format!("Failed to read system time: {e:?}"))?
-        .as_millis();
+fn save_bytes_to_default_dir(
+    app: &tauri::AppHandle,
+    bytes: &[u8],
+    extension: &str,
+) -> Result<String, String> {
+    // An explicitly configured directory beats the Explorer/Downloads guess.
+    let out_dir = match settings::get().save_directory {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => default_output_dir(app)?,
+    };
+    fs::create_dir_all(&out_dir).map_err(|e| format!("Failed to create output dir: {e:?}"))?;
+    open_guard::remember_output_dir(&out_dir);
 
-    let file_name = format!("coppy_{ts}.{extension}");
-    let path = out_dir.join(file_name);
+    let path = filenames::unique_path(&out_dir, &settings::get().filename_pattern, extension);
 
     fs::write(&path, bytes).map_err(|e| format!("Failed to write file: {e:?}"))?;
     Ok(path.to_string_lossy().to_string())
 }
 
+/// Sets where saved images go. An empty `path` goes back to the default
+/// (Explorer folder or Downloads/Coppy).
 #[tauri::command]
-fn save_image_data_url(app: tauri::AppHandle, data_url: String) -> Result<String, String> {
-    let (meta, b64) = data_url
-        .split_once(',')
-        .ok_or_else(|| "Invalid data URL".to_string())?;
+fn set_save_directory(app: tauri::AppHandle, path: String) -> Result<(), String> {
+    let path = path.trim();
+    if path.is_empty() {
+        return settings::update(&app, |s| s.save_directory = None);
+    }
 
-    let mut bytes = general_purpose::STANDARD
-        .decode(b64)
-        .map_err(|e| format!("Failed to decode base64: {e:?}"))?;
+    let dir = std::path::Path::new(path);
+    if !dir.is_dir() {
+        return Err(format!("Not a directory: {path}"));
+    }
+    // Probe with a real write; read-only flags don't cover ACLs or full disks.
+    let probe = dir.join(".coppy_write_test");
+    fs::write(&probe, b"").map_err(|e| format!("Directory is not writable: {e:?}"))?;
+    let _ = fs::remove_file(&probe);
+
+    settings::update(&app, |s| s.save_directory = Some(path.to_string()))
+}
 
-    let mut extension = if meta.contains("image/jpeg") || meta.contains("image/jpg") {
-        "jpg"
+/// Sets the name used for saved files, e.g. "screenshot_{date}_{time}_{n}".
+/// See `filenames::unique_path` for the tokens. Empty restores the default.
+#[tauri::command]
+fn set_filename_pattern(app: tauri::AppHandle, pattern: String) -> Result<(), String> {
+    let pattern = if pattern.trim().is_empty() {
+        filenames::DEFAULT_PATTERN.to_string()
     } else {
-        "png"
+        filenames::validate(&pattern)?;
+        pattern
     };
+    settings::update(&app, |s| s.filename_pattern = pattern)
+}
 
-    // Data URLs come from our own encoder (clipboard_listener) as PNG.
-    // Still, keep a small safety net: if decode/format is odd, re-encode to PNG.
-    if extension == "png" {
-        let img = image::load_from_memory(&bytes)
-            .map_err(|e| format!("Failed to decode image: {e:?}"))?;
-        let mut out = Vec::new();
-        img.write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)
-            .map_err(|e| format!("Failed to encode PNG: {e:?}"))?;
-        bytes = out;
-        extension = "png";
-    }
+#[tauri::command]
+fn save_image_data_url(
+    app: tauri::AppHandle,
+    data_url: String,
+    strip_metadata: Option<bool>,
+) -> Result<String, String> {
+    let format =
+        images::OutputFormat::from_data_url(&data_url).unwrap_or(images::OutputFormat::Png);
+    let bytes = images::decode_data_url_bytes(&data_url)?;
+    // Unless told otherwise, re-encode so metadata such as a photo's GPS
+    // position never reaches the saved file. Otherwise the bytes are kept
+    // as they are, re-encoded only if they don't match the declared format.
+    let bytes = if strip_metadata.unwrap_or(true) {
+        images::strip_metadata(&bytes, format)?
+    } else {
+        images::ensure_format(bytes, format)?
+    };
+    save_bytes_to_default_dir(&app, &bytes, format.extension())
+}
 
-    save_bytes_to_default_dir(&app, &bytes, extension)
+/// Saves an image as "png", "jpg" or "webp". `quality` (1-100) applies to
+/// JPEG only. Always re-encoded, so no source metadata is carried over.
+#[tauri::command]
+fn save_image_data_url_as(
+    app: tauri::AppHandle,
+    data_url: String,
+    format: String,
+    quality: Option<u8>,
+) -> Result<String, String> {
+    let format = images::OutputFormat::parse(&format)?;
+    let img = images::decode_data_url(&data_url)?;
+    let bytes = images::encode(&img, format, quality)?;
+    save_bytes_to_default_dir(&app, &bytes, format.extension())
 }
 
 #[tauri::command]
-fn set_clipboard_text(text: String) -> Result<(), String> {
+fn set_clipboard_text(app: tauri::AppHandle, text: String) -> Result<(), String> {
+    remember_clipboard();
     try_set_clipboard_text(&text)
+        .inspect_err(|e| emit_clipboard_error(&app, "set_clipboard_text", e))
+}
+
+/// Wraps an HTML fragment in the CF_HTML description header, whose offsets
+/// count UTF-8 bytes from the start of the header.
+#[cfg(target_os = "windows")]
+fn cf_html_document(fragment: &str) -> String {
+    const PREFIX: &str = "<html><body>\r\n<!--StartFragment-->";
+    const SUFFIX: &str = "<!--EndFragment-->\r\n</body>\r\n</html>";
+    let header =
+        |start_html: usize, end_html: usize, start_fragment: usize, end_fragment: usize| {
+            format!(
+                "Version:0.9\r\nStartHTML:{start_html:010}\r\nEndHTML:{end_html:010}\r\n\
+             StartFragment:{start_fragment:010}\r\nEndFragment:{end_fragment:010}\r\n"
+            )
+        };
+
+    // The offsets are zero-padded, so the header length doesn't depend on them.
+    let start_html = header(0, 0, 0, 0).len();
+    let start_fragment = start_html + PREFIX.len();
+    let end_fragment = start_fragment + fragment.len();
+    let end_html = end_fragment + SUFFIX.len();
+    format!(
+        "{}{PREFIX}{fragment}{SUFFIX}",
+        header(start_html, end_html, start_fragment, end_fragment)
+    )
+}
+
+/// The Win32 calls behind a clipboard write. Kept behind a trait so the
+/// cleanup on failure can be tested without a real clipboard.
+#[cfg(target_os = "windows")]
+trait ClipboardApi {
+    type Memory: Copy;
+
+    fn open(&mut self) -> Result<(), String>;
+    fn empty(&mut self) -> Result<(), String>;
+    fn close(&mut self);
+    /// Copies `data` into newly allocated global memory.
+    fn alloc(&mut self, data: &[u8]) -> Result<Self::Memory, String>;
+    /// Hands `memory` to the clipboard, which owns it once this succeeds.
+    fn set_data(&mut self, format: u32, memory: Self::Memory) -> Result<(), String>;
+    fn free(&mut self, memory: Self::Memory);
+}
+
+#[cfg(target_os = "windows")]
+struct Win32Clipboard;
+
+#[cfg(target_os = "windows")]
+impl ClipboardApi for Win32Clipboard {
+    type Memory = windows::Win32::Foundation::HGLOBAL;
+
+    fn open(&mut self) -> Result<(), String> {
+        use windows::Win32::Foundation::HWND;
+        use windows::Win32::System::DataExchange::OpenClipboard;
+        unsafe { OpenClipboard(HWND::default()) }
+            .map_err(|e| format!("Failed to open clipboard: {e:?}"))
+    }
+
+    fn empty(&mut self) -> Result<(), String> {
+        use windows::Win32::System::DataExchange::EmptyClipboard;
+        unsafe { EmptyClipboard() }.map_err(|e| format!("Failed to empty clipboard: {e:?}"))
+    }
+
+    fn close(&mut self) {
+        use windows::Win32::System::DataExchange::CloseClipboard;
+        let _ = unsafe { CloseClipboard() };
+    }
+
+    fn alloc(&mut self, data: &[u8]) -> Result<Self::Memory, String> {
+        use std::ptr;
+        use windows::Win32::Foundation::GlobalFree;
+        use windows::Win32::System::Memory::{
+            GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE,
+        };
+
+        unsafe {
+            let hmem = GlobalAlloc(GMEM_MOVEABLE, data.len())
+                .map_err(|e| format!("Failed to allocate memory: {e:?}"))?;
+            let pmem = GlobalLock(hmem);
+            if pmem.is_null() {
+                let _ = GlobalFree(hmem);
+                return Err("Failed to lock memory".to_string());
+            }
+            ptr::copy_nonoverlapping(data.as_ptr(), pmem as *mut u8, data.len());
+            let _ = GlobalUnlock(hmem);
+            Ok(hmem)
+        }
+    }
+
+    fn set_data(&mut self, format: u32, memory: Self::Memory) -> Result<(), String> {
+        use windows::Win32::Foundation::HANDLE;
+        use windows::Win32::System::DataExchange::SetClipboardData;
+        unsafe { SetClipboardData(format, HANDLE(memory.0)) }
+            .map(|_| ())
+            .map_err(|e| format!("Failed to set clipboard data: {e:?}"))
+    }
+
+    fn free(&mut self, memory: Self::Memory) {
+        use windows::Win32::Foundation::GlobalFree;
+        let _ = unsafe { GlobalFree(memory) };
+    }
+}
+
+/// Copies `data` into global memory and hands it to the clipboard, which
+/// must be open and emptied. The memory is freed again if the clipboard
+/// doesn't take it.
+#[cfg(target_os = "windows")]
+fn put_clipboard_data<C: ClipboardApi>(
+    api: &mut C,
+    format: u32,
+    data: &[u8],
+) -> Result<(), String> {
+    let memory = api.alloc(data)?;
+    if let Err(e) = api.set_data(format, memory) {
+        api.free(memory);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// One open/empty/`fill`/close session. The clipboard is closed whatever
+/// `fill` returns.
+#[cfg(target_os = "windows")]
+fn clipboard_session<C: ClipboardApi>(
+    api: &mut C,
+    fill: &mut impl FnMut(&mut C) -> Result<(), String>,
+) -> Result<(), String> {
+    api.open()?;
+    let result = api.empty().and_then(|()| fill(api));
+    api.close();
+    result
+}
+
+/// Opens and empties the clipboard, runs `fill`, and closes it again,
+/// retrying the whole sequence.
+#[cfg(target_os = "windows")]
+fn replace_clipboard(
+    operation: &str,
+    mut fill: impl FnMut(&mut Win32Clipboard) -> Result<(), String>,
+) -> Result<(), String> {
+    with_clipboard_retry(operation, || {
+        clipboard_session(&mut Win32Clipboard, &mut fill)
+    })
+}
+
+/// Replaces the clipboard contents with `data` in clipboard format `format`.
+#[cfg(target_os = "windows")]
+fn with_clipboard_data(format: u32, data: &[u8]) -> Result<(), String> {
+    replace_clipboard("set_clipboard_data", |api| {
+        put_clipboard_data(api, format, data)
+    })
 }
 
+/// Replaces the clipboard contents with `formats` (format id, data) in a
+/// single open/empty/set sequence, so they read as one copy.
+#[cfg(target_os = "windows")]
+fn set_clipboard_formats(formats: &[(u32, Vec<u8>)]) -> Result<(), String> {
+    replace_clipboard("set_clipboard_formats", |api| {
+        formats
+            .iter()
+            .try_for_each(|(format, data)| put_clipboard_data(api, *format, data))
+    })
+}
+
+/// NUL-terminated UTF-16, as CF_UNICODETEXT wants it.
+#[cfg(target_os = "windows")]
+fn unicode_text_bytes(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .chain(std::iter::once(0))
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Puts `text` and `html` on the clipboard together, like a browser copy, so
+/// the target app picks the richest format it understands.
+#[tauri::command]
+fn set_clipboard_rich(app: tauri::AppHandle, text: String, html: String) -> Result<(), String> {
+    remember_clipboard();
+    try_set_clipboard_rich(&text, &html)
+        .inspect_err(|e| emit_clipboard_error(&app, "set_clipboard_rich", e))
+}
+
+fn try_set_clipboard_rich(text: &str, html: &str) -> Result<(), String> {
+    clipboard_listener::mark_self_write_text(text);
+
+    #[cfg(target_os = "windows")]
+    {
+        use windows::core::w;
+        use windows::Win32::System::DataExchange::RegisterClipboardFormatW;
+        use windows::Win32::System::Ole::CF_UNICODETEXT;
+
+        let cf_html = unsafe { RegisterClipboardFormatW(w!("HTML Format")) };
+        let mut html_bytes = cf_html_document(html).into_bytes();
+        html_bytes.push(0);
+        return set_clipboard_formats(&[
+            (CF_UNICODETEXT.0 as u32, unicode_text_bytes(text)),
+            (cf_html, html_bytes),
+        ]);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_html(html, Some(text)))
+        .map_err(|e| format!("Failed to set clipboard HTML: {e:?}"))
+}
+
+/// Puts RTF on the clipboard with `fallback_text` as its plain-text flavor.
+/// Platforms without RTF support get only the text.
+#[tauri::command]
+fn set_clipboard_rtf(
+    app: tauri::AppHandle,
+    rtf: String,
+    fallback_text: String,
+) -> Result<(), String> {
+    remember_clipboard();
+
+    #[cfg(target_os = "windows")]
+    let result = {
+        use windows::core::w;
+        use windows::Win32::System::DataExchange::RegisterClipboardFormatW;
+        use windows::Win32::System::Ole::CF_UNICODETEXT;
+
+        clipboard_listener::mark_self_write_text(&fallback_text);
+        let cf_rtf = unsafe { RegisterClipboardFormatW(w!("Rich Text Format")) };
+        let mut rtf_bytes = rtf.into_bytes();
+        rtf_bytes.push(0);
+        set_clipboard_formats(&[
+            (CF_UNICODETEXT.0 as u32, unicode_text_bytes(&fallback_text)),
+            (cf_rtf, rtf_bytes),
+        ])
+    };
+
+    #[cfg(not(target_os = "windows"))]
+    let result = {
+        let _ = rtf;
+        try_set_clipboard_text(&fallback_text)
+    };
+
+    result.inspect_err(|e| emit_clipboard_error(&app, "set_clipboard_rtf", e))
+}
+
+/// Copies the image as a temp PNG file (CF_HDROP) and, on Windows, as a
+/// bitmap (CF_DIB) in the same clipboard session, so it pastes into Explorer
+/// as a file and into editors and chat apps as an image.
 #[tauri::command]
 fn set_clipboard_image(app: tauri::AppHandle, data_url: String) -> Result<(), String> {
-    eprintln!("set_clipboard_image: start");
+    debug!("set_clipboard_image: start");
 
     let b64 = data_url
         .split_once(',')
@@ -507,20 +730,58 @@ fn set_clipboard_image(app: tauri::AppHandle, data_url: String) -> Result<(), St
         .decode(b64)
         .map_err(|e| format!("Failed to decode base64: {e:?}"))?;
 
+    remember_clipboard();
+
     // Save image to temp file for CF_HDROP (Explorer paste)
     let temp_path = save_image_to_temp(&app, &bytes)?;
-    eprintln!("set_clipboard_image: saved to temp file: {}", temp_path);
+    debug!("set_clipboard_image: saved to temp file: {}", temp_path);
+
+    #[cfg(target_os = "windows")]
+    let result = {
+        use windows::Win32::System::Ole::{CF_DIB, CF_HDROP};
+
+        image::load_from_memory(&bytes)
+            .map_err(|e| format!("Failed to decode image: {e:?}"))
+            .and_then(rgba_parts)
+            .and_then(|(width, height, raw)| {
+                // The listener reads the file list before the bitmap, so
+                // that's what it will see as our echo.
+                clipboard_listener::mark_self_write_files(&[&temp_path]);
+                set_clipboard_formats(&[
+                    (
+                        CF_DIB.0 as u32,
+                        dib_buffer(width as usize, height as usize, &raw),
+                    ),
+                    (CF_HDROP.0 as u32, dropfiles_buffer(&[&temp_path])),
+                ])
+            })
+    };
 
-    // Set clipboard with CF_HDROP (file drop) for Explorer
-    set_clipboard_file(&temp_path)?;
+    #[cfg(not(target_os = "windows"))]
+    let result = set_clipboard_files(&[&temp_path]);
+
+    result.inspect_err(|e| emit_clipboard_error(&app, "set_clipboard_image", e))?;
 
-    eprintln!("set_clipboard_image: done");
+    debug!("set_clipboard_image: done");
     Ok(())
 }
 
+/// Puts the image itself on the clipboard as a bitmap, for "Copy";
+/// `set_clipboard_image` is "Copy as file".
+#[tauri::command]
+fn copy_image_to_clipboard(app: tauri::AppHandle, data_url: String) -> Result<(), String> {
+    decode_image_data_url(&data_url)
+        .and_then(|(width, height, raw)| {
+            remember_clipboard();
+            try_set_clipboard_image(width as usize, height as usize, raw)
+        })
+        .inspect_err(|e| emit_clipboard_error(&app, "copy_image_to_clipboard", e))
+}
+
 #[cfg(target_os = "windows")]
 fn save_image_to_temp(app: &tauri::AppHandle, bytes: &[u8]) -> Result<String, String> {
     use std::io::Cursor;
+    use std::time::{SystemTime, UNIX_EPOCH};
 
     // Decode and re-encode as PNG to ensure valid format
     let img =
@@ -553,196 +814,1299 @@ fn save_image_to_temp(_app: &tauri::AppHandle, _bytes: &[u8]) -> Result<String,
     Err("Not implemented on this platform".to_string())
 }
 
+/// CF_HDROP payload: a DROPFILES header followed by each path as UTF-16 with
+/// a null terminator, and one more null ending the list.
 #[cfg(target_os = "windows")]
-fn set_clipboard_file(file_path: &str) -> Result<(), String> {
-    use std::ptr;
-    use windows::Win32::Foundation::HWND;
-    use windows::Win32::System::DataExchange::{
-        CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData,
-    };
-    use windows::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
-    use windows::Win32::System::Ole::CF_HDROP;
-
-    // Convert path to wide string (UTF-16) with null terminator
-    let wide_path: Vec<u16> = file_path.encode_utf16().chain(std::iter::once(0)).collect();
-
-    // DROPFILES structure size (20 bytes) + file path (UTF-16) + double null terminator
-    let dropfiles_size = 20usize;
-    let path_bytes = wide_path.len() * 2; // Each UTF-16 char is 2 bytes
-    let total_size = dropfiles_size + path_bytes + 2; // +2 for extra null terminator
-
-    unsafe {
-        // Open clipboard
-        if OpenClipboard(HWND::default()).is_err() {
-            return Err("Failed to open clipboard".to_string());
-        }
-
-        // Empty clipboard
-        if EmptyClipboard().is_err() {
-            let _ = CloseClipboard();
-            return Err("Failed to empty clipboard".to_string());
-        }
+fn dropfiles_buffer(paths: &[&str]) -> Vec<u8> {
+    // DROPFILES structure
+    // pFiles (4 bytes): offset to file list = 20 (size of DROPFILES)
+    // pt.x (4 bytes): 0
+    // pt.y (4 bytes): 0
+    // fNC (4 bytes): 0
+    // fWide (4 bytes): 1 (Unicode)
+    const DROPFILES_SIZE: usize = 20;
+    let dropfiles: [u8; DROPFILES_SIZE] = [
+        20, 0, 0, 0, // pFiles = 20
+        0, 0, 0, 0, // pt.x = 0
+        0, 0, 0, 0, // pt.y = 0
+        0, 0, 0, 0, // fNC = 0
+        1, 0, 0, 0, // fWide = 1 (TRUE)
+    ];
 
-        // Allocate global memory
-        let hmem = match GlobalAlloc(GMEM_MOVEABLE, total_size) {
-            Ok(h) => h,
-            Err(e) => {
-                let _ = CloseClipboard();
-                return Err(format!("Failed to allocate memory: {e:?}"));
-            }
-        };
+    let wide: Vec<u16> = paths
+        .iter()
+        .flat_map(|path| path.encode_utf16().chain(std::iter::once(0)))
+        .chain(std::iter::once(0))
+        .collect();
 
-        let pmem = GlobalLock(hmem);
-        if pmem.is_null() {
-            let _ = CloseClipboard();
-            return Err("Failed to lock memory".to_string());
-        }
+    let mut buf = Vec::with_capacity(DROPFILES_SIZE + wide.len() * 2);
+    buf.extend_from_slice(&dropfiles);
+    for unit in wide {
+        buf.extend_from_slice(&unit.to_le_bytes());
+    }
+    buf
+}
 
-        // DROPFILES structure
-        // pFiles (4 bytes): offset to file list = 20 (size of DROPFILES)
-        // pt.x (4 bytes): 0
-        // pt.y (4 bytes): 0
-        // fNC (4 bytes): 0
-        // fWide (4 bytes): 1 (Unicode)
-        let dropfiles: [u8; 20] = [
-            20, 0, 0, 0, // pFiles = 20
-            0, 0, 0, 0, // pt.x = 0
-            0, 0, 0, 0, // pt.y = 0
-            0, 0, 0, 0, // fNC = 0
-            1, 0, 0, 0, // fWide = 1 (TRUE)
-        ];
+#[cfg(target_os = "windows")]
+fn set_clipboard_files(paths: &[&str]) -> Result<(), String> {
+    use windows::Win32::System::Ole::CF_HDROP;
 
-        ptr::copy_nonoverlapping(dropfiles.as_ptr(), pmem as *mut u8, 20);
+    clipboard_listener::mark_self_write_files(paths);
 
-        // Copy file path as UTF-16
-        let path_dest = (pmem as *mut u8).add(20) as *mut u16;
-        ptr::copy_nonoverlapping(wide_path.as_ptr(), path_dest, wide_path.len());
+    with_clipboard_data(CF_HDROP.0 as u32, &dropfiles_buffer(paths))?;
 
-        // Add extra null terminator at the end
-        let end = path_dest.add(wide_path.len());
-        *end = 0;
+    debug!(
+        "set_clipboard_files: CF_HDROP set with {} path(s)",
+        paths.len()
+    );
+    Ok(())
+}
 
-        let _ = GlobalUnlock(hmem);
+#[cfg(not(target_os = "windows"))]
+fn set_clipboard_files(_paths: &[&str]) -> Result<(), String> {
+    Err("Not implemented on this platform".to_string())
+}
 
-        // Set clipboard data - CF_HDROP = 15
-        let result = SetClipboardData(
-            CF_HDROP.0 as u32,
-            windows::Win32::Foundation::HANDLE(hmem.0),
-        );
-        let _ = CloseClipboard();
+#[derive(Clone)]
+enum ClipboardSnapshot {
+    Text(String),
+    Image {
+        width: usize,
+        height: usize,
+        bytes: Vec<u8>,
+    },
+    /// The clipboard was known to hold nothing at all.
+    Empty,
+}
 
-        if result.is_err() {
-            return Err("Failed to set clipboard data".to_string());
-        }
+/// What's on the clipboard, or `None` when it holds something that can't be
+/// put back (files, RTF only, ...) or can't be read.
+fn snapshot_clipboard() -> Option<ClipboardSnapshot> {
+    let mut clipboard = Clipboard::new().ok()?;
+    if let Ok(text) = clipboard.get_text() {
+        return Some(ClipboardSnapshot::Text(text));
+    }
+    if let Ok(img) = clipboard.get_image() {
+        return Some(ClipboardSnapshot::Image {
+            width: img.width,
+            height: img.height,
+            bytes: img.bytes.into_owned(),
+        });
+    }
+    clipboard_is_empty().then_some(ClipboardSnapshot::Empty)
+}
 
-        eprintln!("set_clipboard_file: CF_HDROP set successfully");
-        Ok(())
+/// Whether the clipboard holds no format at all. Other platforms can't tell
+/// an empty clipboard from one holding formats arboard doesn't read, so they
+/// never report it empty.
+fn clipboard_is_empty() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::System::DataExchange::CountClipboardFormats;
+        return unsafe { CountClipboardFormats() } == 0;
     }
+
+    #[cfg(not(target_os = "windows"))]
+    false
 }
 
-#[cfg(not(target_os = "windows"))]
-fn set_clipboard_file(_file_path: &str) -> Result<(), String> {
-    Err("Not implemented on this platform".to_string())
+// What the clipboard held before Coppy last wrote to it.
+static PREVIOUS_CLIPBOARD: Mutex<Option<ClipboardSnapshot>> = Mutex::new(None);
+
+/// Snapshots the clipboard ahead of a write so `restore_previous_clipboard`
+/// can undo it. Returns the snapshot as well.
+fn remember_clipboard() -> Option<ClipboardSnapshot> {
+    let snapshot = snapshot_clipboard();
+    *PREVIOUS_CLIPBOARD
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = snapshot.clone();
+    snapshot
 }
 
+/// Puts back what was on the clipboard before Coppy's last write (a copy or
+/// paste from history). Works once per write.
 #[tauri::command]
-fn paste_text(app: tauri::AppHandle, text: String) -> Result<(), String> {
-    eprintln!("paste_text: start");
+fn restore_previous_clipboard(app: tauri::AppHandle) -> Result<(), String> {
+    let snapshot = PREVIOUS_CLIPBOARD
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+        .ok_or("Nothing to restore")?;
+    restore_clipboard(snapshot)
+        .inspect_err(|e| emit_clipboard_error(&app, "restore_previous_clipboard", e))
+}
 
-    if let Some(window) = app.get_webview_window("main") {
-        let _ = window.hide();
+fn restore_clipboard(snapshot: ClipboardSnapshot) -> Result<(), String> {
+    match snapshot {
+        ClipboardSnapshot::Text(text) => try_set_clipboard_text(&text),
+        ClipboardSnapshot::Image {
+            width,
+            height,
+            bytes,
+        } => try_set_clipboard_image(width, height, bytes),
+        ClipboardSnapshot::Empty => try_clear_clipboard(),
     }
+}
 
-    if let Err(err) = try_set_clipboard_text(&text) {
-        eprintln!("paste_text: {err}");
-        return Err(err);
+/// Applies the post-paste clipboard setting. Runs on a background thread after
+/// a delay so the target app has time to read the pasted content first.
+fn finish_post_paste(mode: settings::PostPasteClipboard, snapshot: Option<ClipboardSnapshot>) {
+    if mode == settings::PostPasteClipboard::Keep {
+        return;
     }
 
-    #[cfg(target_os = "windows")]
-    {
-        key_listener::focus_last_foreground_window();
-    }
+    std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(500));
 
-    std::thread::sleep(std::time::Duration::from_millis(320));
+        let result = match (mode, snapshot) {
+            (settings::PostPasteClipboard::Restore, Some(snapshot)) => restore_clipboard(snapshot),
+            // The clipboard held something that can't be put back (e.g.
+            // copied files); leaving the pasted content beats wiping it.
+            (settings::PostPasteClipboard::Restore, None) => Ok(()),
+            _ => try_clear_clipboard(),
+        };
+        if let Err(e) = result {
+            warn!("finish_post_paste: {e}");
+        }
+    });
+}
 
+/// Title of the window a paste is about to land in, for the paste log.
+fn paste_target() -> Option<String> {
     #[cfg(target_os = "windows")]
     {
-        send_ctrl_v().map_err(|e| format!("Failed to send Ctrl+V: {e}"))?;
+        return key_listener::last_foreground_window_title();
     }
 
     #[cfg(not(target_os = "windows"))]
-    {
-        let mut enigo =
-            Enigo::new(&Settings::default()).map_err(|e| format!("Failed to init enigo: {e:?}"))?;
-        let _ = enigo.key(Key::Control, enigo::Direction::Press);
-        let _ = enigo.key(Key::Unicode('v'), enigo::Direction::Click);
-        let _ = enigo.key(Key::Control, enigo::Direction::Release);
-    }
-    eprintln!("paste_text: done");
-    Ok(())
+    None
 }
 
 #[tauri::command]
-fn paste_image(app: tauri::AppHandle, data_url: String) -> Result<(), String> {
-    eprintln!("paste_image: start");
-
+fn assess_paste_target() -> Result<key_listener::PasteTargetAssessment, String> {
+    #[cfg(target_os = "windows")]
+    {
+        return key_listener::assess_paste_target();
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    Err("Paste target assessment is only supported on Windows".to_string())
+}
+
+/// Health of the clipboard watcher, keyboard hook and history, plus the
+/// most recent error.
+#[tauri::command]
+fn get_status(app: tauri::AppHandle) -> status::AppStatus {
+    status::collect(&app)
+}
+
+/// Whether the Windows double-tap hook is running; always false elsewhere.
+#[tauri::command]
+fn keyboard_hook_active() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        return key_listener::is_hook_installed();
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    false
+}
+
+#[tauri::command]
+fn clear_clipboard() -> Result<(), String> {
+    try_clear_clipboard()
+}
+
+#[tauri::command]
+fn paste_text(app: tauri::AppHandle, text: String, item_id: Option<String>) -> Result<(), String> {
+    paste_text_with(&app, text, item_id, false)
+}
+
+/// Pastes `text`, along with the HTML of history item `item_id` if the item
+/// prefers it (see `set_preferred_format`).
+fn paste_text_with(
+    app: &tauri::AppHandle,
+    text: String,
+    item_id: Option<String>,
+    keep_open: bool,
+) -> Result<(), String> {
+    let html = item_id
+        .as_deref()
+        .and_then(|id| history::preferred_html(app, id));
+    paste_with(app, "paste_text", item_id, keep_open, || match &html {
+        Some(html) => try_set_clipboard_rich(&text, html),
+        None => try_set_clipboard_text(&text),
+    })
+}
+
+/// Like `paste_text`, but Coppy's window stays open for pasting several items
+/// in a row.
+#[tauri::command]
+fn paste_text_keep_open(
+    app: tauri::AppHandle,
+    text: String,
+    item_id: Option<String>,
+) -> Result<(), String> {
+    paste_text_with(&app, text, item_id, true)
+}
+
+/// Adds the current clipboard text to the paste stack and returns the stack.
+#[tauri::command]
+fn push_to_stack() -> Result<Vec<String>, String> {
+    stack::push_clipboard()
+}
+
+#[tauri::command]
+fn get_stack() -> Vec<String> {
+    stack::get()
+}
+
+#[tauri::command]
+fn clear_stack() {
+    stack::clear();
+}
+
+/// Pastes everything on the stack joined with `separator`, then empties it.
+#[tauri::command]
+fn paste_stack(app: tauri::AppHandle, separator: String) -> Result<(), String> {
+    let text = stack::joined(&separator).ok_or("The paste stack is empty")?;
+    paste_with(&app, "paste_stack", None, false, || {
+        try_set_clipboard_text(&text)
+    })?;
+    stack::clear();
+    Ok(())
+}
+
+/// Pastes `text` with every other clipboard format removed first, so the
+/// target can't pick up HTML/RTF left behind by an earlier copy.
+#[tauri::command]
+fn paste_plain_text(
+    app: tauri::AppHandle,
+    text: String,
+    item_id: Option<String>,
+) -> Result<(), String> {
+    paste_with(&app, "paste_plain_text", item_id, false, || {
+        try_set_plain_clipboard_text(&text)
+    })
+}
+
+/// Pastes files (e.g. a "files" history item) as if copied in Explorer.
+#[tauri::command]
+fn paste_files(
+    app: tauri::AppHandle,
+    paths: Vec<String>,
+    item_id: Option<String>,
+) -> Result<(), String> {
+    if paths.is_empty() {
+        return Err("No files to paste".to_string());
+    }
+    if let Some(missing) = paths
+        .iter()
+        .find(|path| !std::path::Path::new(path).exists())
+    {
+        return Err(format!("File not found: {missing}"));
+    }
+
+    let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+    paste_with(&app, "paste_files", item_id, false, || {
+        set_clipboard_files(&paths)
+    })
+}
+
+/// Replaces everything on the clipboard with just `text`, so no richer
+/// flavor of an earlier copy is left behind for the target to prefer.
+fn try_set_plain_clipboard_text(text: &str) -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::System::Ole::CF_UNICODETEXT;
+
+        // One open/empty/set session, so nothing can slip in between.
+        clipboard_listener::mark_self_write_text(text);
+        return with_clipboard_data(CF_UNICODETEXT.0 as u32, &unicode_text_bytes(text));
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        try_clear_clipboard()?;
+        try_set_clipboard_text(text)
+    }
+}
+
+/// Pastes the `n`th most recent history item (1 is the newest) into the
+/// previously focused window.
+#[tauri::command]
+fn paste_history_index(app: tauri::AppHandle, n: usize) -> Result<(), String> {
+    let items = history::load(&app)?;
+    let item = n
+        .checked_sub(1)
+        .and_then(|index| items.into_iter().nth(index))
+        .ok_or_else(|| format!("No history item at position {n}"))?;
+    let item_id = Some(item.id.clone());
+
+    match item.item_type.as_str() {
+        "image" => {
+            let rgba = decode_image_data_url(&history::full_content(&app, &item)?)?;
+            paste_rgba(&app, "paste_image", item_id, rgba, false)
+        }
+        "files" => paste_files(
+            app,
+            item.content.lines().map(str::to_string).collect(),
+            item_id,
+        ),
+        _ => {
+            let text = history::full_content(&app, &item)?;
+            paste_text(app, text, item_id)
+        }
+    }
+}
+
+// How long a paste waits for the target window to get focus back.
+#[cfg(target_os = "windows")]
+const PASTE_FOCUS_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(1500);
+
+/// Sends Ctrl+V (Cmd+V on macOS) with enigo. On Wayland enigo usually can't inject input, so
+/// this goes through `wtype` or `ydotool` instead.
+#[cfg(not(target_os = "windows"))]
+fn send_paste_keys() -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    if is_wayland() {
+        return wayland_paste();
+    }
+
+    let mut enigo =
+        Enigo::new(&Settings::default()).map_err(|e| format!("Failed to init enigo: {e:?}"))?;
+    enigo
+        .key(PASTE_MODIFIER, enigo::Direction::Press)
+        .map_err(|e| format!("Failed to send paste keys: {e:?}"))?;
+    let click = enigo.key(Key::Unicode('v'), enigo::Direction::Click);
+    // Release the modifier even if the click failed so it doesn't stick.
+    let release = enigo.key(PASTE_MODIFIER, enigo::Direction::Release);
+    click
+        .and(release)
+        .map_err(|e| format!("Failed to send paste keys: {e:?}"))
+}
+
+#[cfg(target_os = "linux")]
+fn is_wayland() -> bool {
+    std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session.eq_ignore_ascii_case("wayland"))
+}
+
+/// Tries each Wayland input tool in turn. `wtype` needs the compositor's
+/// virtual-keyboard protocol (not on GNOME); `ydotool` needs its daemon.
+#[cfg(target_os = "linux")]
+fn wayland_paste() -> Result<(), String> {
+    let tools: [(&str, &[&str]); 2] = [
+        ("wtype", &["-M", "ctrl", "v", "-m", "ctrl"]),
+        // Linux keycodes: 29 is Left Ctrl, 47 is V.
+        ("ydotool", &["key", "29:1", "47:1", "47:0", "29:0"]),
+    ];
+
+    let mut failures = Vec::new();
+    for (tool, args) in tools {
+        match std::process::Command::new(tool).args(args).output() {
+            Ok(output) if output.status.success() => return Ok(()),
+            Ok(output) => failures.push(format!(
+                "{tool} exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => failures.push(format!("Failed to run {tool}: {e:?}")),
+        }
+    }
+
+    if failures.is_empty() {
+        Err("Pasting on Wayland needs wtype or ydotool, and neither is installed".to_string())
+    } else {
+        Err(format!(
+            "Failed to send Ctrl+V on Wayland: {}",
+            failures.join("; ")
+        ))
+    }
+}
+
+// How long the target gets to handle Ctrl+V before a kept-open window takes
+// focus back.
+const KEEP_OPEN_REFOCUS_DELAY: std::time::Duration = std::time::Duration::from_millis(150);
+
+/// Gets the window out of the way of a paste. With `keep_open` it stays up
+/// on Windows, where `focus_paste_target` can move focus past it; elsewhere
+/// focus only goes back to the target once Coppy is hidden, so
+/// `refocus_after_paste` shows it again.
+fn hide_for_paste(app: &tauri::AppHandle, keep_open: bool) {
+    if keep_open && cfg!(target_os = "windows") {
+        return;
+    }
     if let Some(window) = app.get_webview_window("main") {
         let _ = window.hide();
     }
+    // Hiding only the window leaves Coppy the active app on macOS; hiding the
+    // app hands focus back to whichever app was frontmost before.
+    #[cfg(target_os = "macos")]
+    let _ = app.hide();
+}
 
-    let b64 = data_url
-        .split_once(',')
-        .map(|(_, b64)| b64)
-        .ok_or_else(|| "Invalid data URL".to_string())?;
-    let bytes = general_purpose::STANDARD
-        .decode(b64)
-        .map_err(|e| format!("Failed to decode base64: {e:?}"))?;
+/// Brings a kept-open window back to the front once the paste has landed.
+/// The paste target stays recorded, so the next paste goes to the same app.
+fn refocus_after_paste(app: &tauri::AppHandle) {
+    std::thread::sleep(KEEP_OPEN_REFOCUS_DELAY);
+    #[cfg(target_os = "macos")]
+    let _ = app.show();
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Puts content on the clipboard with `set_clipboard`, then sends Ctrl+V to
+/// the previously focused window. With `keep_open` Coppy's window is back in
+/// front afterwards.
+fn paste_with(
+    app: &tauri::AppHandle,
+    operation: &str,
+    item_id: Option<String>,
+    keep_open: bool,
+    set_clipboard: impl FnOnce() -> Result<(), String>,
+) -> Result<(), String> {
+    debug!("{operation}: start");
+
+    let window = app.get_webview_window("main");
+    let was_visible = window
+        .as_ref()
+        .map(|w| w.is_visible().unwrap_or(false))
+        .unwrap_or(false);
+    hide_for_paste(app, keep_open);
+
+    let post_paste = settings::get().post_paste_clipboard;
+    let snapshot =
+        remember_clipboard().filter(|_| post_paste == settings::PostPasteClipboard::Restore);
+
+    if let Err(err) = set_clipboard() {
+        warn!("{operation}: {err}");
+        emit_clipboard_error(app, operation, &err);
+        if was_visible {
+            if let Some(window) = &window {
+                let _ = window.show();
+                let _ = window.set_focus();
+            }
+        }
+        return Err(err);
+    }
+
+    #[cfg(target_os = "windows")]
+    if let Err(err) = key_listener::focus_paste_target(PASTE_FOCUS_TIMEOUT) {
+        warn!("{operation}: {err}");
+        emit_clipboard_error(app, operation, &err);
+        return Err(err);
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    std::thread::sleep(std::time::Duration::from_millis(320));
 
-    let img = image::load_from_memory(&bytes)
-        .map_err(|e| format!("Failed to decode image: {e:?}"))?
-        .to_rgba8();
+    #[cfg(target_os = "windows")]
+    if let Err(e) = send_ctrl_v() {
+        let err = format!("Failed to send Ctrl+V: {e}");
+        warn!("{operation}: {err}");
+        emit_clipboard_error(app, operation, &err);
+        return Err(err);
+    }
 
+    #[cfg(not(target_os = "windows"))]
+    if let Err(err) = send_paste_keys() {
+        warn!("{operation}: {err}");
+        emit_clipboard_error(app, operation, &err);
+        return Err(err);
+    }
+
+    finish_post_paste(post_paste, snapshot);
+    pastes::record(app, item_id, paste_target());
+    if keep_open {
+        refocus_after_paste(app);
+    }
+
+    debug!("{operation}: done");
+    Ok(())
+}
+
+fn decode_image_data_url(data_url: &str) -> Result<(u32, u32, Vec<u8>), String> {
+    rgba_parts(images::decode_data_url(data_url)?)
+}
+
+fn rgba_parts(img: image::DynamicImage) -> Result<(u32, u32, Vec<u8>), String> {
+    let img = img.to_rgba8();
     let (width, height) = img.dimensions();
     let raw = img.into_raw();
 
-    try_set_clipboard_image(width as usize, height as usize, raw)?;
+    // CF_DIB and arboard both expect tightly packed RGBA rows.
+    let expected = width as usize * height as usize * 4;
+    if width == 0 || height == 0 || raw.len() != expected {
+        return Err(format!(
+            "Decoded image has unexpected size: {width}x{height}, {} bytes (expected {expected})",
+            raw.len()
+        ));
+    }
 
-    #[cfg(target_os = "windows")]
+    Ok((width, height, raw))
+}
+
+/// Puts already-decoded RGBA pixels on the clipboard and pastes them into the
+/// previously focused window with `paste_with`.
+fn paste_rgba(
+    app: &tauri::AppHandle,
+    operation: &str,
+    item_id: Option<String>,
+    (width, height, raw): (u32, u32, Vec<u8>),
+    keep_open: bool,
+) -> Result<(), String> {
+    paste_with(app, operation, item_id, keep_open, move || {
+        try_set_clipboard_image(width as usize, height as usize, raw)
+    })
+}
+
+fn paste_image_data_url(
+    app: &tauri::AppHandle,
+    data_url: &str,
+    item_id: Option<String>,
+    keep_open: bool,
+) -> Result<(), String> {
+    // Decode before touching the window so a bad image leaves the popup open
+    // and the caller can show the error.
+    let rgba = decode_image_data_url(data_url).inspect_err(|e| warn!("paste_image: {e}"))?;
+    paste_rgba(app, "paste_image", item_id, rgba, keep_open)
+}
+
+#[tauri::command]
+fn paste_image(
+    app: tauri::AppHandle,
+    data_url: String,
+    item_id: Option<String>,
+) -> Result<(), String> {
+    paste_image_data_url(&app, &data_url, item_id, false)
+}
+
+/// Like `paste_image`, but Coppy's window stays open for the next paste.
+#[tauri::command]
+fn paste_image_keep_open(
+    app: tauri::AppHandle,
+    data_url: String,
+    item_id: Option<String>,
+) -> Result<(), String> {
+    paste_image_data_url(&app, &data_url, item_id, true)
+}
+
+/// Like `paste_image`, but first downscales the image to fit within
+/// `max_dim` x `max_dim`, keeping its aspect ratio.
+#[tauri::command]
+fn paste_image_resized(
+    app: tauri::AppHandle,
+    data_url: String,
+    max_dim: u32,
+    item_id: Option<String>,
+) -> Result<(), String> {
+    if max_dim == 0 {
+        return Err("max_dim must be greater than 0".to_string());
+    }
+
+    let rgba = images::decode_data_url(&data_url)
+        .map(|img| images::fit_within(img, max_dim, max_dim))
+        .and_then(rgba_parts)
+        .inspect_err(|e| warn!("paste_image_resized: {e}"))?;
+    paste_rgba(&app, "paste_image_resized", item_id, rgba, false)
+}
+
+/// Captures a region of the screen (in physical screen coordinates) into
+/// history and returns it as a data URL.
+#[tauri::command]
+fn capture_screen_region(
+    app: tauri::AppHandle,
+    x: i32,
+    y: i32,
+    w: u32,
+    h: u32,
+) -> Result<String, String> {
+    #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
     {
-        key_listener::focus_last_foreground_window();
+        if w == 0 || h == 0 {
+            return Err("Capture region must not be empty".to_string());
+        }
+
+        let monitor =
+            xcap::Monitor::from_point(x, y).map_err(|e| format!("No monitor at {x},{y}: {e:?}"))?;
+        let geometry_err = |e: xcap::XCapError| format!("Failed to read monitor geometry: {e:?}");
+        let mon_x = monitor.x().map_err(geometry_err)?;
+        let mon_y = monitor.y().map_err(geometry_err)?;
+        let mon_w = monitor.width().map_err(geometry_err)?;
+        let mon_h = monitor.height().map_err(geometry_err)?;
+
+        // xcap wants coordinates relative to the monitor, and the region has
+        // to stay on it.
+        let rel_x = (x - mon_x) as u32;
+        let rel_y = (y - mon_y) as u32;
+        if rel_x.saturating_add(w) > mon_w || rel_y.saturating_add(h) > mon_h {
+            return Err(format!(
+                "Capture region {w}x{h} at {x},{y} extends past the monitor bounds"
+            ));
+        }
+
+        let captured = monitor
+            .capture_region(rel_x, rel_y, w, h)
+            .map_err(|e| format!("Failed to capture screen: {e:?}"))?;
+        let (width, height) = captured.dimensions();
+        let img = arboard::ImageData {
+            width: width as usize,
+            height: height as usize,
+            bytes: std::borrow::Cow::Owned(captured.into_raw()),
+        };
+
+        return clipboard_listener::record_image(&app, img)
+            .ok_or_else(|| "Failed to encode captured image".to_string());
     }
 
-    std::thread::sleep(std::time::Duration::from_millis(320));
+    #[cfg(not(any(target_os = "macos", windows, target_os = "linux")))]
+    {
+        let _ = (app, x, y, w, h);
+        Err("Screen capture is not supported on this platform".to_string())
+    }
+}
+
+#[tauri::command]
+fn recent_pastes(app: tauri::AppHandle) -> Vec<pastes::PasteRecord> {
+    pastes::recent(&app)
+}
+
+#[tauri::command]
+fn load_favorites(app: tauri::AppHandle) -> Result<favorites::FavoritesState, String> {
+    favorites::load(&app)
+}
+
+#[tauri::command]
+fn save_favorites(app: tauri::AppHandle, state: favorites::FavoritesState) -> Result<(), String> {
+    favorites::save(&app, state)
+}
+
+/// Unlocks encrypted favorites, or encrypts plain ones, with `pass`. An empty
+/// `pass` turns encryption off.
+#[tauri::command]
+fn set_favorites_passphrase(app: tauri::AppHandle, pass: String) -> Result<(), String> {
+    favorites::set_passphrase(&app, &pass)
+}
+
+/// Adds `item` unless a favorite with the same content exists. Returns the
+/// updated favorites.
+#[tauri::command]
+fn add_favorite(
+    app: tauri::AppHandle,
+    item: favorites::FavoriteItem,
+) -> Result<favorites::FavoritesState, String> {
+    favorites::add(&app, item)
+}
+
+/// Fuzzy search over `scope` (`"all"`, `"favorites"` or `"history"`), with
+/// the matched character positions for highlighting.
+#[tauri::command]
+fn fuzzy_search(
+    app: tauri::AppHandle,
+    query: String,
+    scope: String,
+) -> Result<Vec<search::ScoredItem>, String> {
+    search::fuzzy(&app, &query, &scope)
+}
+
+#[tauri::command]
+fn search_favorites(
+    app: tauri::AppHandle,
+    query: String,
+    folder_id: Option<String>,
+) -> Result<Vec<favorites::FavoriteItem>, String> {
+    favorites::search(&app, &query, folder_id.as_deref())
+}
 
+#[tauri::command]
+fn rename_favorite(
+    app: tauri::AppHandle,
+    id: String,
+    name: String,
+) -> Result<favorites::FavoritesState, String> {
+    favorites::rename(&app, &id, &name)
+}
+
+#[tauri::command]
+fn move_favorite_to_folder(
+    app: tauri::AppHandle,
+    item_id: String,
+    folder_id: Option<String>,
+) -> Result<favorites::FavoritesState, String> {
+    favorites::move_to_folder(&app, &item_id, folder_id.as_deref())
+}
+
+#[tauri::command]
+fn create_folder(app: tauri::AppHandle, name: String) -> Result<favorites::FavoriteFolder, String> {
+    favorites::create_folder(&app, &name)
+}
+
+#[tauri::command]
+fn rename_folder(
+    app: tauri::AppHandle,
+    id: String,
+    name: String,
+) -> Result<favorites::FavoritesState, String> {
+    favorites::rename_folder(&app, &id, &name)
+}
+
+/// Deletes a folder, moving its items to `reassign_to` (the root if `None`).
+#[tauri::command]
+fn delete_folder(
+    app: tauri::AppHandle,
+    id: String,
+    reassign_to: Option<String>,
+) -> Result<favorites::FavoritesState, String> {
+    favorites::delete_folder(&app, &id, reassign_to.as_deref())
+}
+
+#[tauri::command]
+fn reorder_favorites(
+    app: tauri::AppHandle,
+    ordered_ids: Vec<String>,
+) -> Result<favorites::FavoritesState, String> {
+    favorites::reorder_items(&app, &ordered_ids)
+}
+
+#[tauri::command]
+fn reorder_favorite_folders(
+    app: tauri::AppHandle,
+    ordered_ids: Vec<String>,
+) -> Result<favorites::FavoritesState, String> {
+    favorites::reorder_folders(&app, &ordered_ids)
+}
+
+#[tauri::command]
+fn export_favorites(app: tauri::AppHandle) -> Result<String, String> {
+    favorites::export(&app)
+}
+
+#[tauri::command]
+fn import_favorites(
+    app: tauri::AppHandle,
+    json: String,
+    merge: bool,
+) -> Result<favorites::FavoritesState, String> {
+    favorites::import(&app, &json, merge)
+}
+
+#[tauri::command]
+fn remove_favorite(app: tauri::AppHandle, id: String) -> Result<favorites::FavoritesState, String> {
+    favorites::remove(&app, &id)
+}
+
+#[tauri::command]
+fn load_history(app: tauri::AppHandle) -> Result<Vec<history::HistoryItem>, String> {
+    history::load(&app)
+}
+
+/// Adds an entry created outside the clipboard listener (e.g. by the UI).
+#[tauri::command]
+fn append_history_item(app: tauri::AppHandle, item: history::HistoryItem) -> Result<(), String> {
+    history::append(&app, item).map(|_| ())
+}
+
+/// Sets how many history entries are kept (default 200). Oldest entries
+/// that aren't favorites are evicted first.
+#[tauri::command]
+fn set_max_history_items(app: tauri::AppHandle, max: usize) -> Result<(), String> {
+    if max == 0 {
+        return Err("History size must be at least 1".to_string());
+    }
+    settings::update(&app, |s| s.max_history_items = max)?;
+    history::enforce_limit(&app)
+}
+
+/// Caps the total size of image entries in history (default 50MB); 0
+/// removes the cap.
+#[tauri::command]
+fn set_max_history_image_bytes(app: tauri::AppHandle, bytes: u64) -> Result<(), String> {
+    settings::update(&app, |s| s.max_history_image_bytes = bytes)?;
+    history::enforce_limit(&app)
+}
+
+/// Full-resolution data URL of a previewed image, by the `image_hash` of its
+/// clipboard update (the `image_file` of its history item).
+#[tauri::command]
+fn get_full_image(app: tauri::AppHandle, hash: String) -> Result<String, String> {
+    history::full_image(&app, &hash)
+}
+
+/// Sets how many times, and starting how far apart, clipboard writes are
+/// retried.
+#[tauri::command]
+fn set_clipboard_retry(app: tauri::AppHandle, attempts: u32, delay_ms: u64) -> Result<(), String> {
+    settings::update(&app, |s| {
+        s.clipboard_retry_attempts = attempts.max(1);
+        s.clipboard_retry_delay_ms = delay_ms;
+    })
+}
+
+/// Whole text of a truncated capture, by the `text_hash` of its clipboard
+/// update (the `text_file` of its history item).
+#[tauri::command]
+fn get_full_text(app: tauri::AppHandle, hash: String) -> Result<String, String> {
+    history::full_text(&app, &hash)
+}
+
+/// Sets how many characters of captured text are kept inline; 0 keeps all
+/// of it.
+#[tauri::command]
+fn set_max_text_length(app: tauri::AppHandle, n: usize) -> Result<(), String> {
+    settings::update(&app, |s| s.max_text_length = n)
+}
+
+/// Sets the longest side of captured image previews; 0 sends images at full
+/// resolution.
+#[tauri::command]
+fn set_image_preview_max_px(app: tauri::AppHandle, px: u32) -> Result<(), String> {
+    settings::update(&app, |s| s.image_preview_max_px = px)
+}
+
+#[tauri::command]
+fn convert_history_image(
+    app: tauri::AppHandle,
+    id: String,
+    format: String,
+    quality: Option<u8>,
+) -> Result<String, String> {
+    let format = images::OutputFormat::parse(&format)?;
+    let item = history::find(&app, &id)?;
+    if item.item_type != "image" {
+        return Err(format!("History item is not an image: {id}"));
+    }
+
+    let img = images::decode_data_url(&history::full_content(&app, &item)?)?;
+    let bytes = images::encode(&img, format, quality)?;
+    Ok(images::to_data_url(&bytes, format))
+}
+
+/// Scales an image down to `max_width` x `max_height` (keeping its aspect
+/// ratio when `keep_aspect`) and returns it as a PNG data URL for
+/// `paste_image`. Only enlarges with `allow_upscale`.
+#[tauri::command]
+fn resize_image(
+    data_url: String,
+    max_width: u32,
+    max_height: u32,
+    keep_aspect: bool,
+    allow_upscale: Option<bool>,
+) -> Result<String, String> {
+    let img = images::decode_data_url(&data_url)?;
+    let resized = images::resize(
+        img,
+        max_width,
+        max_height,
+        keep_aspect,
+        allow_upscale.unwrap_or(false),
+    )?;
+    let bytes = images::encode(&resized, images::OutputFormat::Png, None)?;
+    Ok(images::to_data_url(&bytes, images::OutputFormat::Png))
+}
+
+/// Crops an image to the `w` x `h` rectangle at (`x`, `y`) and returns it as
+/// a PNG data URL, ready for `paste_image` or `save_image_data_url`.
+#[tauri::command]
+fn crop_image(data_url: String, x: u32, y: u32, w: u32, h: u32) -> Result<String, String> {
+    let img = images::decode_data_url(&data_url)?;
+    let cropped = images::crop(&img, x, y, w, h)?;
+    let bytes = images::encode(&cropped, images::OutputFormat::Png, None)?;
+    Ok(images::to_data_url(&bytes, images::OutputFormat::Png))
+}
+
+/// Encodes `text` as a QR code and returns it as a PNG data URL, which
+/// `save_image_data_url` can save.
+#[tauri::command]
+fn text_to_qr_png(text: String) -> Result<String, String> {
+    let img = images::qr_code(&text)?;
+    let bytes = images::encode(&img, images::OutputFormat::Png, None)?;
+    Ok(images::to_data_url(&bytes, images::OutputFormat::Png))
+}
+
+/// Extracts the text from an image with the platform OCR engine. Slow, so
+/// only run on request; async to keep it off the main thread.
+#[tauri::command]
+async fn ocr_image(data_url: String) -> Result<String, String> {
+    ocr::recognize(images::decode_data_url(&data_url)?)
+}
+
+/// Expands the `{date}`, `{time}`, `{clipboard}` and `{cursor}` tokens of a
+/// snippet (usually a favorite) before it's pasted.
+#[tauri::command]
+fn expand_snippet(text: String) -> text::ExpandedSnippet {
+    let clipboard = if text.contains("{clipboard}") {
+        Clipboard::new()
+            .and_then(|mut clipboard| clipboard.get_text())
+            .unwrap_or_default()
+    } else {
+        String::new()
+    };
+    let now = time::OffsetDateTime::now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc());
+    text::expand_snippet(&text, &clipboard, now)
+}
+
+/// Pretty-prints JSON for the "Format" action.
+#[tauri::command]
+fn format_json(text: String) -> Result<String, String> {
+    text::format_json(&text)
+}
+
+/// Language id of a code snippet for the UI's syntax highlighter, or `None`
+/// when it doesn't look like code.
+#[tauri::command]
+fn detect_language(text: String) -> Option<String> {
+    text::detect_language(&text).map(str::to_string)
+}
+
+/// Character, word and line counts for a text item.
+#[tauri::command]
+fn text_stats(text: String) -> text::TextStats {
+    text::stats(&text)
+}
+
+/// Returns `text` with `op` applied (e.g. "uppercase", "base64_decode"), for
+/// the UI to preview or copy. See `text::transform` for the ops.
+#[tauri::command]
+fn transform_text(text: String, op: String) -> Result<String, String> {
+    text::transform(&text, &op)
+}
+
+#[tauri::command]
+fn diff_items(
+    app: tauri::AppHandle,
+    id_a: String,
+    id_b: String,
+) -> Result<Vec<text::DiffLine>, String> {
+    let a = history::find(&app, &id_a)?;
+    let b = history::find(&app, &id_b)?;
+    if a.item_type == "image" || b.item_type == "image" {
+        return Err("Only text items can be compared".to_string());
+    }
+    // `content` is only the start of a truncated text.
+    let a = history::full_content(&app, &a)?;
+    let b = history::full_content(&app, &b)?;
+    Ok(text::diff_lines(&a, &b))
+}
+
+#[tauri::command]
+fn delete_history_item(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    history::remove(&app, &id)
+}
+
+#[tauri::command]
+fn clear_history(app: tauri::AppHandle) -> Result<(), String> {
+    history::clear(&app)
+}
+
+#[tauri::command]
+fn set_item_no_sync(app: tauri::AppHandle, id: String, no_sync: bool) -> Result<(), String> {
+    history::set_no_sync(&app, &id, no_sync)
+}
+
+/// Picks the flavor (`"text"` or `"html"`) a history item pastes as by
+/// default; `None` goes back to plain text.
+#[tauri::command]
+fn set_preferred_format(
+    app: tauri::AppHandle,
+    item_id: String,
+    format: Option<String>,
+) -> Result<(), String> {
+    let format = format
+        .as_deref()
+        .map(history::PasteFormat::parse)
+        .transpose()?;
+    history::set_preferred_format(&app, &item_id, format)
+}
+
+#[tauri::command]
+fn set_item_expiry(app: tauri::AppHandle, id: String, ttl_ms: Option<u64>) -> Result<(), String> {
+    history::set_expiry(&app, &id, ttl_ms)
+}
+
+#[tauri::command]
+fn get_settings() -> settings::Settings {
+    settings::get()
+}
+
+#[tauri::command]
+fn set_post_paste_clipboard(app: tauri::AppHandle, mode: String) -> Result<(), String> {
+    let mode = settings::PostPasteClipboard::parse(&mode)?;
+    settings::update(&app, |s| s.post_paste_clipboard = mode)
+}
+
+#[tauri::command]
+fn set_recopy_behavior(app: tauri::AppHandle, behavior: String) -> Result<(), String> {
+    let behavior = settings::RecopyBehavior::parse(&behavior)?;
+    settings::update(&app, |s| s.recopy_behavior = behavior)
+}
+
+#[tauri::command]
+fn set_capture_on_startup(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    settings::update(&app, |s| s.capture_on_startup = enabled)
+}
+
+#[tauri::command]
+fn set_restore_last_on_startup(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    settings::update(&app, |s| s.restore_last_on_startup = enabled)
+}
+
+#[tauri::command]
+fn set_low_memory_mode(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    settings::update(&app, |s| s.low_memory_mode = enabled)?;
+    history::enforce_limit(&app)
+}
+
+#[tauri::command]
+fn get_clipboard_poll_interval() -> u64 {
+    clipboard_listener::poll_interval_ms()
+}
+
+#[tauri::command]
+fn set_clipboard_poll_interval(ms: u64) -> u64 {
+    clipboard_listener::set_poll_interval_ms(ms)
+}
+
+/// Current clipboard content, for filling the UI without waiting for the
+/// next `clipboard-update` event. Type `"empty"` when there's nothing to read.
+#[tauri::command]
+fn read_clipboard() -> Result<clipboard_listener::ClipboardUpdate, String> {
+    clipboard_listener::read_current()
+}
+
+#[tauri::command]
+fn pause_monitoring(app: tauri::AppHandle) {
+    clipboard_listener::set_paused(true);
+    #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
+    tray::sync(&app);
+    #[cfg(not(any(target_os = "macos", windows, target_os = "linux")))]
+    let _ = app;
+}
+
+#[tauri::command]
+fn resume_monitoring(app: tauri::AppHandle) {
+    clipboard_listener::set_paused(false);
+    #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
+    tray::sync(&app);
+    #[cfg(not(any(target_os = "macos", windows, target_os = "linux")))]
+    let _ = app;
+}
+
+#[tauri::command]
+fn is_monitoring_paused() -> bool {
+    clipboard_listener::is_paused()
+}
+
+/// Turns whitespace normalization of captured text on or off. The text as
+/// copied is kept on the history item as `original`.
+#[tauri::command]
+fn set_trim_captured_text(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    settings::update(&app, |s| s.trim_captured_text = enabled)
+}
+
+/// Replaces the list of apps whose copies aren't recorded. An empty list
+/// records everything.
+#[tauri::command]
+fn set_ignored_apps(app: tauri::AppHandle, names: Vec<String>) -> Result<(), String> {
+    let names = names
+        .into_iter()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    settings::update(&app, |s| s.ignored_apps = names)
+}
+
+/// Keeps copies from ignored apps for `ttl_ms` before they expire instead
+/// of dropping them; 0 drops them again.
+#[tauri::command]
+fn set_ignored_app_expiry(app: tauri::AppHandle, ttl_ms: u64) -> Result<(), String> {
+    settings::update(&app, |s| s.ignored_app_expiry_ms = ttl_ms)
+}
+
+#[tauri::command]
+fn set_log_level(app: tauri::AppHandle, level: String) -> Result<(), String> {
+    logging::set_level(&level)?;
+    settings::update(&app, |s| s.log_level = level)
+}
+
+#[tauri::command]
+fn open_log_file(app: tauri::AppHandle) -> Result<(), String> {
+    use tauri_plugin_opener::OpenerExt;
+
+    let path = logging::current_log_file(&app)?;
+    app.opener()
+        .open_path(path.to_string_lossy(), None::<&str>)
+        .map_err(|e| format!("Failed to open log file: {e:?}"))
+}
+
+#[tauri::command]
+fn open_path(app: tauri::AppHandle, path: String) -> Result<(), String> {
+    use tauri_plugin_opener::OpenerExt;
+
+    let path = open_guard::check(&app, &path)?;
+    app.opener()
+        .open_path(path.to_string_lossy(), None::<&str>)
+        .map_err(|e| format!("Failed to open path: {e:?}"))
+}
+
+#[tauri::command]
+fn reveal_in_explorer(app: tauri::AppHandle, path: String) -> Result<(), String> {
+    use tauri_plugin_opener::OpenerExt;
+
+    let path = open_guard::check(&app, &path)?;
+    app.opener()
+        .reveal_item_in_dir(path)
+        .map_err(|e| format!("Failed to reveal path: {e:?}"))
+}
+
+/// Shows a path that was copied as text in the file manager: a folder is
+/// opened, anything else is selected in its folder. Nothing gets launched,
+/// so unlike `reveal_in_explorer` this isn't limited to Coppy's folders.
+#[tauri::command]
+fn open_in_explorer(app: tauri::AppHandle, path: String) -> Result<(), String> {
+    use tauri_plugin_opener::OpenerExt;
+
+    let path = std::path::Path::new(path.trim())
+        .canonicalize()
+        .map_err(|e| format!("Cannot open {path}: {e}"))?;
+    // App bundles are folders with an extension, and opening one would
+    // launch it.
+    if path.is_dir() && path.extension().is_none() {
+        app.opener()
+            .open_path(path.to_string_lossy(), None::<&str>)
+            .map_err(|e| format!("Failed to open folder: {e:?}"))
+    } else {
+        app.opener()
+            .reveal_item_in_dir(path)
+            .map_err(|e| format!("Failed to reveal path: {e:?}"))
+    }
+}
+
+#[tauri::command]
+fn set_allow_arbitrary_open(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    settings::update(&app, |s| s.allow_arbitrary_open = enabled)
+}
+
+#[tauri::command]
+fn list_profiles(app: tauri::AppHandle) -> Result<profiles::ProfilesState, String> {
+    profiles::list(&app)
+}
+
+#[tauri::command]
+fn create_profile(app: tauri::AppHandle, name: String) -> Result<(), String> {
+    profiles::create(&app, &name)
+}
+
+#[tauri::command]
+fn switch_profile(app: tauri::AppHandle, name: String) -> Result<(), String> {
+    profiles::switch(&app, &name)
+}
+
+/// Reports whether `combo` (e.g. "CmdOrCtrl+Shift+V") is already taken by the
+/// system or another app, by briefly registering it and releasing it again.
+#[tauri::command]
+fn check_shortcut_conflict(app: tauri::AppHandle, combo: String) -> Result<bool, String> {
+    #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
+    {
+        use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut};
+
+        let shortcut: Shortcut = combo
+            .parse()
+            .map_err(|e| format!("Invalid shortcut {combo}: {e:?}"))?;
+        let global_shortcut = app.global_shortcut();
+
+        // Already registered by us, so not a conflict.
+        if global_shortcut.is_registered(shortcut) {
+            return Ok(false);
+        }
+
+        return match global_shortcut.register(shortcut) {
+            Ok(()) => {
+                let _ = global_shortcut.unregister(shortcut);
+                Ok(false)
+            }
+            Err(_) => Ok(true),
+        };
+    }
+
+    #[cfg(not(any(target_os = "macos", windows, target_os = "linux")))]
+    {
+        let _ = (app, combo);
+        Err("Global shortcuts are not supported on this platform".to_string())
+    }
+}
+
+/// Sets the double-Ctrl detection window in ms; 0 disables the trigger.
+#[tauri::command]
+fn set_double_ctrl_threshold_ms(app: tauri::AppHandle, ms: u64) -> Result<(), String> {
     #[cfg(target_os = "windows")]
+    key_listener::set_double_ctrl_threshold_ms(ms);
+
+    settings::update(&app, |s| s.double_ctrl_threshold_ms = ms)
+}
+
+/// Picks the modifier watched for the double-tap trigger: "ctrl", "shift",
+/// "alt" or "win".
+#[tauri::command]
+fn set_double_tap_key(app: tauri::AppHandle, key: String) -> Result<(), String> {
+    let key = settings::DoubleTapKey::parse(&key)?;
+
+    #[cfg(target_os = "windows")]
+    key_listener::set_double_tap_key(key);
+
+    settings::update(&app, |s| s.double_tap_key = key)
+}
+
+#[tauri::command]
+fn set_toggle_shortcut(app: tauri::AppHandle, combo: String) -> Result<(), String> {
+    #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
     {
-        send_ctrl_v().map_err(|e| format!("Failed to send Ctrl+V: {e}"))?;
+        return hotkey::set_toggle_shortcut(&app, &combo);
     }
 
-    eprintln!("paste_image: done");
-    Ok(())
+    #[cfg(not(any(target_os = "macos", windows, target_os = "linux")))]
+    {
+        let _ = (app, combo);
+        Err("Global shortcuts are not supported on this platform".to_string())
+    }
 }
 
+/// Saves the main window's size, and its position if `remember_position`,
+/// for the next time it opens.
 #[tauri::command]
-fn load_favorites(app: tauri::AppHandle) -> Result<FavoritesState, String> {
-    let path = favorites_file_path(&app)?;
-    if !path.exists() {
-        return Ok(FavoritesState {
-            folders: Vec::new(),
-            items: Vec::new(),
-        });
+fn save_window_geometry(app: tauri::AppHandle, remember_position: bool) -> Result<(), String> {
+    window::save_geometry(&app, remember_position)
+}
+
+/// Turns the CmdOrCtrl+Alt+1-9 quick-paste shortcuts on or off.
+#[tauri::command]
+fn set_quick_paste_shortcuts(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
+    {
+        return hotkey::set_quick_paste(&app, enabled);
     }
-    let raw = fs::read_to_string(&path).map_err(|e| format!("Failed to read favorites: {e:?}"))?;
-    serde_json::from_str(&raw).map_err(|e| format!("Failed to parse favorites: {e:?}"))
+
+    #[cfg(not(any(target_os = "macos", windows, target_os = "linux")))]
+    {
+        let _ = (app, enabled);
+        Err("Global shortcuts are not supported on this platform".to_string())
+    }
+}
+
+/// Passed by the login item registered with `autostart_enable`.
+const AUTOSTART_ARG: &str = "--autostart";
+
+fn launched_by_autostart() -> bool {
+    std::env::args().skip(1).any(|arg| arg == AUTOSTART_ARG)
 }
 
+/// Whether this instance was started at login rather than by the user, so
+/// the UI can stay quiet (no onboarding, no focus grabbing).
 #[tauri::command]
-fn save_favorites(app: tauri::AppHandle, state: FavoritesState) -> Result<(), String> {
-    let path = favorites_file_path(&app)?;
-    let raw = serde_json::to_string(&state)
-        .map_err(|e| format!("Failed to serialize favorites: {e:?}"))?;
-    fs::write(&path, raw).map_err(|e| format!("Failed to write favorites: {e:?}"))?;
-    Ok(())
+fn launched_via_autostart() -> bool {
+    launched_by_autostart()
 }
 
 #[tauri::command]
@@ -768,10 +2132,12 @@ fn autostart_enable(app: tauri::AppHandle) -> Result<(), String> {
     #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
     {
         use tauri_plugin_autostart::ManagerExt;
-        return app
+        let result = app
             .autolaunch()
             .enable()
             .map_err(|e| format!("Failed to enable autostart: {e:?}"));
+        tray::sync(&app);
+        return result;
     }
 
     #[cfg(not(any(target_os = "macos", windows, target_os = "linux")))]
@@ -786,10 +2152,12 @@ fn autostart_disable(app: tauri::AppHandle) -> Result<(), String> {
     #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
     {
         use tauri_plugin_autostart::ManagerExt;
-        return app
+        let result = app
             .autolaunch()
             .disable()
             .map_err(|e| format!("Failed to disable autostart: {e:?}"));
+        tray::sync(&app);
+        return result;
     }
 
     #[cfg(not(any(target_os = "macos", windows, target_os = "linux")))]
@@ -800,38 +2168,335 @@ fn autostart_disable(app: tauri::AppHandle) -> Result<(), String> {
 }
 
 mod clipboard_listener;
+mod crypto;
+mod favorites;
+mod filenames;
+mod history;
+#[cfg(any(target_os = "macos", windows, target_os = "linux"))]
+mod hotkey;
+mod images;
 mod key_listener;
+mod logging;
+mod ocr;
+mod open_guard;
+mod pastes;
+mod profiles;
+mod search;
+mod settings;
+mod stack;
+mod status;
+mod text;
+#[cfg(any(target_os = "macos", windows, target_os = "linux"))]
+mod tray;
+mod window;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let builder = tauri::Builder::default();
 
     #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
-    let builder = { builder.plugin(tauri_plugin_autostart::Builder::new().build()) };
+    let builder = {
+        builder.plugin(
+            tauri_plugin_autostart::Builder::new()
+                .args([AUTOSTART_ARG])
+                .build(),
+        )
+    };
+
+    #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
+    let builder = { builder.plugin(tauri_plugin_global_shortcut::Builder::new().build()) };
 
     builder
         .setup(|app| {
+            logging::init(app.handle());
+            settings::init(app.handle());
+            if let Err(e) = logging::set_level(&settings::get().log_level) {
+                warn!("{e}");
+            }
+            profiles::init(app.handle());
+
+            // Starting at login should never flash the window, whatever the
+            // window config says.
+            if launched_by_autostart() {
+                if let Some(window) = app.get_webview_window("main") {
+                    let _ = window.hide();
+                }
+            }
+
             #[cfg(target_os = "windows")]
-            key_listener::start_listening(app.handle().clone());
+            {
+                let settings = settings::get();
+                key_listener::set_double_ctrl_threshold_ms(settings.double_ctrl_threshold_ms);
+                key_listener::set_double_tap_key(settings.double_tap_key);
+                key_listener::start_listening(app.handle().clone());
+            }
+            #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
+            hotkey::init(app.handle());
+            #[cfg(any(target_os = "macos", windows, target_os = "linux"))]
+            if let Err(e) = tray::init(app.handle()) {
+                warn!("{e}");
+            }
 
             clipboard_listener::start(app.handle().clone());
+            history::start_expiry_sweeper(app.handle().clone());
+
+            std::thread::spawn(warmup);
 
             Ok(())
         })
         .plugin(tauri_plugin_opener::init())
         .invoke_handler(tauri::generate_handler![
             simulate_paste,
+            warmup,
             set_clipboard_text,
+            set_clipboard_rich,
+            set_clipboard_rtf,
+            copy_image_to_clipboard,
             set_clipboard_image,
+            clear_clipboard,
+            restore_previous_clipboard,
             paste_text,
+            paste_plain_text,
+            paste_text_keep_open,
+            paste_image_keep_open,
+            push_to_stack,
+            get_stack,
+            clear_stack,
+            paste_stack,
+            paste_files,
+            paste_history_index,
             paste_image,
+            paste_image_resized,
+            recent_pastes,
+            assess_paste_target,
+            keyboard_hook_active,
+            get_status,
+            capture_screen_region,
             save_image_data_url,
+            save_image_data_url_as,
+            set_save_directory,
+            set_filename_pattern,
             load_favorites,
             save_favorites,
+            set_favorites_passphrase,
+            add_favorite,
+            remove_favorite,
+            search_favorites,
+            fuzzy_search,
+            rename_favorite,
+            move_favorite_to_folder,
+            create_folder,
+            rename_folder,
+            delete_folder,
+            reorder_favorites,
+            reorder_favorite_folders,
+            export_favorites,
+            import_favorites,
+            load_history,
+            append_history_item,
+            delete_history_item,
+            clear_history,
+            set_max_history_items,
+            set_max_history_image_bytes,
+            set_item_expiry,
+            set_item_no_sync,
+            set_preferred_format,
+            get_full_image,
+            get_full_text,
+            set_clipboard_retry,
+            set_max_text_length,
+            set_image_preview_max_px,
+            convert_history_image,
+            text_to_qr_png,
+            crop_image,
+            resize_image,
+            ocr_image,
+            transform_text,
+            text_stats,
+            format_json,
+            detect_language,
+            expand_snippet,
+            diff_items,
+            get_settings,
+            set_post_paste_clipboard,
+            set_recopy_behavior,
+            set_restore_last_on_startup,
+            set_capture_on_startup,
+            set_low_memory_mode,
+            get_clipboard_poll_interval,
+            set_clipboard_poll_interval,
+            read_clipboard,
+            pause_monitoring,
+            resume_monitoring,
+            is_monitoring_paused,
+            set_ignored_apps,
+            set_ignored_app_expiry,
+            set_trim_captured_text,
+            set_log_level,
+            open_log_file,
+            open_path,
+            reveal_in_explorer,
+            open_in_explorer,
+            set_allow_arbitrary_open,
+            list_profiles,
+            create_profile,
+            switch_profile,
+            check_shortcut_conflict,
+            set_toggle_shortcut,
+            set_quick_paste_shortcuts,
+            save_window_geometry,
+            set_double_ctrl_threshold_ms,
+            set_double_tap_key,
+            launched_via_autostart,
             autostart_is_enabled,
             autostart_enable,
             autostart_disable
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                clipboard_listener::stop();
+            }
+        });
+}
+
+// Everything covered here is Windows clipboard plumbing.
+#[cfg(all(test, target_os = "windows"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropfiles_buffer_lists_two_paths() {
+        let buf = dropfiles_buffer(&["C:\\a", "D:\\bc"]);
+
+        let mut expected = vec![
+            20, 0, 0, 0, // pFiles: the list starts right after the header
+            0, 0, 0, 0, // pt.x
+            0, 0, 0, 0, // pt.y
+            0, 0, 0, 0, // fNC
+            1, 0, 0, 0, // fWide
+        ];
+        for unit in "C:\\a\0D:\\bc\0\0".encode_utf16() {
+            expected.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(buf, expected);
+        // A single null between the paths, two at the end.
+        assert_eq!(&buf[28..30], &[0, 0]);
+        assert_eq!(&buf[buf.len() - 4..], &[0, 0, 0, 0]);
+    }
+
+    /// Records the calls made on it and fails the ones it's told to.
+    #[derive(Default)]
+    struct FakeClipboard {
+        calls: Vec<String>,
+        fail_alloc: bool,
+        fail_set_format: Option<u32>,
+        next_memory: u32,
+    }
+
+    impl ClipboardApi for FakeClipboard {
+        type Memory = u32;
+
+        fn open(&mut self) -> Result<(), String> {
+            self.calls.push("open".to_string());
+            Ok(())
+        }
+
+        fn empty(&mut self) -> Result<(), String> {
+            self.calls.push("empty".to_string());
+            Ok(())
+        }
+
+        fn close(&mut self) {
+            self.calls.push("close".to_string());
+        }
+
+        fn alloc(&mut self, _data: &[u8]) -> Result<u32, String> {
+            if self.fail_alloc {
+                self.calls.push("alloc failed".to_string());
+                return Err("Failed to allocate memory".to_string());
+            }
+            self.next_memory += 1;
+            self.calls.push(format!("alloc {}", self.next_memory));
+            Ok(self.next_memory)
+        }
+
+        fn set_data(&mut self, format: u32, memory: u32) -> Result<(), String> {
+            if self.fail_set_format == Some(format) {
+                self.calls.push(format!("set {format} failed"));
+                return Err("Failed to set clipboard data".to_string());
+            }
+            self.calls.push(format!("set {format} {memory}"));
+            Ok(())
+        }
+
+        fn free(&mut self, memory: u32) {
+            self.calls.push(format!("free {memory}"));
+        }
+    }
+
+    #[test]
+    fn failed_set_frees_memory_and_closes_clipboard() {
+        let mut api = FakeClipboard {
+            fail_set_format: Some(13),
+            ..Default::default()
+        };
+        let result = clipboard_session(&mut api, &mut |api| put_clipboard_data(api, 13, b"hi"));
+
+        assert!(result.is_err());
+        assert_eq!(
+            api.calls,
+            [
+                "open",
+                "empty",
+                "alloc 1",
+                "set 13 failed",
+                "free 1",
+                "close"
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_alloc_closes_clipboard() {
+        let mut api = FakeClipboard {
+            fail_alloc: true,
+            ..Default::default()
+        };
+        let result = clipboard_session(&mut api, &mut |api| put_clipboard_data(api, 13, b"hi"));
+
+        assert!(result.is_err());
+        assert_eq!(api.calls, ["open", "empty", "alloc failed", "close"]);
+    }
+
+    #[test]
+    fn failed_second_format_frees_only_its_own_memory() {
+        let mut api = FakeClipboard {
+            fail_set_format: Some(49),
+            ..Default::default()
+        };
+        let formats = [(13, b"hi".to_vec()), (49, b"<b>hi</b>".to_vec())];
+        let result = clipboard_session(&mut api, &mut |api| {
+            formats
+                .iter()
+                .try_for_each(|(format, data)| put_clipboard_data(api, *format, data))
+        });
+
+        assert!(result.is_err());
+        // The clipboard owns the first block; only the second is freed.
+        assert_eq!(
+            api.calls,
+            [
+                "open",
+                "empty",
+                "alloc 1",
+                "set 13 1",
+                "alloc 2",
+                "set 49 failed",
+                "free 2",
+                "close"
+            ]
+        );
+    }
 }
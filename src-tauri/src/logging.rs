@@ -0,0 +1,189 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use tauri::AppHandle;
+use time::{Date, OffsetDateTime};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{reload, Registry};
+
+use crate::profiles;
+
+const LOG_FILE_PREFIX: &str = "coppy";
+// Daily files, so this caps logs at roughly a week.
+const MAX_LOG_FILES: usize = 7;
+// A day's file stops growing at this size, so `logs/` stays under
+// MAX_LOG_FILES * MAX_LOG_FILE_BYTES (70 MiB) even at trace level.
+const MAX_LOG_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+static LEVEL_HANDLE: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+// Dropping the guard stops the background writer, so keep it for the app's lifetime.
+static WRITER_GUARD: OnceLock<WorkerGuard> = OnceLock::new();
+
+pub fn logs_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = profiles::app_data_dir(app)?.join("logs");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create logs dir: {e:?}"))?;
+    Ok(dir)
+}
+
+/// Name the daily appender gives the file for `day` (UTC).
+fn daily_file_name(day: Date) -> String {
+    format!(
+        "{LOG_FILE_PREFIX}.{:04}-{:02}-{:02}.log",
+        day.year(),
+        day.month() as u8,
+        day.day()
+    )
+}
+
+/// Passes writes through to the daily appender until the day's file reaches
+/// `MAX_LOG_FILE_BYTES`, then drops the rest of that day's output.
+struct CappedWriter<W> {
+    inner: W,
+    dir: PathBuf,
+    day: Option<Date>,
+    written: u64,
+}
+
+impl<W: Write> CappedWriter<W> {
+    fn new(inner: W, dir: PathBuf) -> Self {
+        Self {
+            inner,
+            dir,
+            day: None,
+            written: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for CappedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let today = OffsetDateTime::now_utc().date();
+        if self.day != Some(today) {
+            self.day = Some(today);
+            // Output from earlier today (before a restart) counts too.
+            self.written = fs::metadata(self.dir.join(daily_file_name(today)))
+                .map(|m| m.len())
+                .unwrap_or(0);
+        }
+        if self.written >= MAX_LOG_FILE_BYTES {
+            // Report the bytes as written so the caller doesn't retry.
+            return Ok(buf.len());
+        }
+        self.inner.write_all(buf)?;
+        self.written += buf.len() as u64;
+        if self.written >= MAX_LOG_FILE_BYTES {
+            self.inner
+                .write_all(b"log size limit reached, dropping the rest of today's output\n")?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Sends `tracing` output to stderr and to daily-rotated files under
+/// `logs/` in the app data dir, each capped at `MAX_LOG_FILE_BYTES`.
+pub fn init(app: &AppHandle) {
+    let (filter, handle) = reload::Layer::new(LevelFilter::INFO);
+
+    let file_layer = logs_dir(app).and_then(|dir| {
+        RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(LOG_FILE_PREFIX)
+            .filename_suffix("log")
+            .max_log_files(MAX_LOG_FILES)
+            .build(&dir)
+            .map(|appender| CappedWriter::new(appender, dir))
+            .map_err(|e| format!("Failed to create log file: {e:?}"))
+    });
+    let file_layer = match file_layer {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let _ = WRITER_GUARD.set(guard);
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(writer)
+                    .with_ansi(false),
+            )
+        }
+        Err(e) => {
+            eprintln!("logging: {e}, logging to stderr only");
+            None
+        }
+    };
+
+    let result = tracing_subscriber::registry()
+        .with(filter)
+        .with(file_layer)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .try_init();
+    if result.is_ok() {
+        let _ = LEVEL_HANDLE.set(handle);
+    }
+}
+
+/// Sets the minimum level: "error", "warn", "info", "debug", "trace" or "off".
+pub fn set_level(level: &str) -> Result<(), String> {
+    let level: LevelFilter = level
+        .parse()
+        .map_err(|_| format!("Unknown log level: {level}"))?;
+    let handle = LEVEL_HANDLE
+        .get()
+        .ok_or_else(|| "Logging is not initialized".to_string())?;
+    handle
+        .modify(|filter| *filter = level)
+        .map_err(|e| format!("Failed to set log level: {e:?}"))
+}
+
+/// Most recently written log file, if any.
+pub fn current_log_file(app: &AppHandle) -> Result<PathBuf, String> {
+    let entries =
+        fs::read_dir(logs_dir(app)?).map_err(|e| format!("Failed to read logs dir: {e:?}"))?;
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(LOG_FILE_PREFIX)
+        })
+        .filter_map(|entry| {
+            let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+            Some((modified, entry.path()))
+        })
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+        .ok_or_else(|| "No log file has been written yet".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_stop_at_the_daily_cap() {
+        let dir = std::env::temp_dir().join(format!("coppy-logging-{}", std::process::id()));
+        let mut writer = CappedWriter::new(Vec::new(), dir);
+        let line = vec![b'x'; 1024 * 1024];
+        for _ in 0..12 {
+            assert_eq!(writer.write(&line).unwrap(), line.len());
+        }
+
+        let out = writer.inner;
+        assert!(out.ends_with(b"dropping the rest of today's output\n"));
+        assert!(out.len() < MAX_LOG_FILE_BYTES as usize + 100);
+    }
+
+    #[test]
+    fn file_name_matches_the_daily_appender() {
+        let day = Date::from_calendar_date(2026, time::Month::March, 5).unwrap();
+        assert_eq!(daily_file_name(day), "coppy.2026-03-05.log");
+    }
+}
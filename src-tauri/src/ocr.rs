@@ -0,0 +1,79 @@
+use image::DynamicImage;
+
+/// Recognizes the text in `img` with the platform OCR engine. Only Windows
+/// has one built in (Windows.Media.Ocr); elsewhere this is an error.
+pub fn recognize(img: DynamicImage) -> Result<String, String> {
+    #[cfg(target_os = "windows")]
+    {
+        // WinRT needs COM initialized, which a command thread can't be
+        // relied on to have.
+        return std::thread::spawn(move || windows_ocr::recognize(img))
+            .join()
+            .map_err(|_| "OCR failed unexpectedly".to_string())?;
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = img;
+        Err("OCR is not available on this platform".to_string())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows_ocr {
+    use image::DynamicImage;
+    use windows::Graphics::Imaging::{BitmapPixelFormat, SoftwareBitmap};
+    use windows::Media::Ocr::OcrEngine;
+    use windows::Storage::Streams::DataWriter;
+    use windows::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_MULTITHREADED};
+
+    use crate::images;
+
+    pub fn recognize(img: DynamicImage) -> Result<String, String> {
+        let initialized = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok();
+        let result = run(img);
+        if initialized {
+            unsafe { CoUninitialize() };
+        }
+        result
+    }
+
+    fn run(img: DynamicImage) -> Result<String, String> {
+        let engine = OcrEngine::TryCreateFromUserProfileLanguages().map_err(|_| {
+            "No OCR language is installed; add one in Windows language settings".to_string()
+        })?;
+
+        let max = OcrEngine::MaxImageDimension()
+            .map_err(|e| format!("Failed to query OCR engine: {e:?}"))?;
+        let gray = images::fit_within(img, max, max).to_luma8();
+
+        let writer = DataWriter::new().map_err(|e| format!("Failed to prepare image: {e:?}"))?;
+        writer
+            .WriteBytes(gray.as_raw())
+            .map_err(|e| format!("Failed to prepare image: {e:?}"))?;
+        let buffer = writer
+            .DetachBuffer()
+            .map_err(|e| format!("Failed to prepare image: {e:?}"))?;
+        let bitmap = SoftwareBitmap::CreateCopyFromBuffer(
+            &buffer,
+            BitmapPixelFormat::Gray8,
+            gray.width() as i32,
+            gray.height() as i32,
+        )
+        .map_err(|e| format!("Failed to prepare image: {e:?}"))?;
+
+        let result = engine
+            .RecognizeAsync(&bitmap)
+            .and_then(|operation| operation.get())
+            .map_err(|e| format!("OCR failed: {e:?}"))?;
+        // `OcrResult::Text` joins lines with spaces; keep the line breaks.
+        let lines = result
+            .Lines()
+            .map_err(|e| format!("OCR failed: {e:?}"))?
+            .into_iter()
+            .filter_map(|line| line.Text().ok())
+            .map(|text| text.to_string())
+            .collect::<Vec<_>>();
+        Ok(lines.join("\n"))
+    }
+}
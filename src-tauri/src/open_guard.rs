@@ -0,0 +1,187 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use tauri::{AppHandle, Manager};
+
+use crate::{profiles, settings};
+
+// Never opened unless arbitrary opens are explicitly allowed, even inside an
+// allowed directory.
+const BLOCKED_EXTENSIONS: &[&str] = &[
+    "exe", "com", "bat", "cmd", "msi", "ps1", "vbs", "vbe", "js", "jse", "wsf", "scr", "lnk",
+    "hta", "cpl", "reg", "sh", "app", "command",
+];
+
+// Folders Coppy has written output to this session (e.g. an Explorer folder
+// picked up at save time), in addition to the fixed allowlist.
+static OUTPUT_DIRS: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+pub fn remember_output_dir(dir: &Path) {
+    if let Ok(dir) = dir.canonicalize() {
+        OUTPUT_DIRS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert_with(HashSet::new)
+            .insert(dir);
+    }
+}
+
+fn allowed_dirs(app: &AppHandle) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Ok(dir) = profiles::app_data_dir(app) {
+        dirs.push(dir);
+    }
+    if let Ok(dir) = app.path().download_dir() {
+        dirs.push(dir.join("Coppy"));
+    }
+    with_output_dirs(dirs, settings::get().save_directory.as_deref())
+}
+
+/// `dirs` plus the configured save directory and this session's output
+/// folders, canonicalized. Missing folders are dropped.
+fn with_output_dirs(mut dirs: Vec<PathBuf>, save_directory: Option<&str>) -> Vec<PathBuf> {
+    dirs.extend(
+        OUTPUT_DIRS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .flatten()
+            .cloned(),
+    );
+    // Files saved there in an earlier session stay openable after a restart.
+    dirs.extend(save_directory.map(PathBuf::from));
+
+    dirs.into_iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .collect()
+}
+
+/// Resolves `path` and checks that it may be opened: it must exist, live
+/// inside one of Coppy's own folders and not be an executable, unless the
+/// user has allowed arbitrary opens.
+pub fn check(app: &AppHandle, path: &str) -> Result<PathBuf, String> {
+    if settings::get().allow_arbitrary_open {
+        return resolve(path);
+    }
+    check_within(path, &allowed_dirs(app))
+}
+
+// Canonicalizing resolves `..` segments and symlinks, so a path can't climb
+// out of an allowed folder.
+fn resolve(path: &str) -> Result<PathBuf, String> {
+    Path::new(path)
+        .canonicalize()
+        .map_err(|e| format!("Cannot open {path}: {e}"))
+}
+
+/// `check` against an already canonical `allowed` list.
+fn check_within(path: &str, allowed: &[PathBuf]) -> Result<PathBuf, String> {
+    let resolved = resolve(path)?;
+    let blocked = resolved
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| BLOCKED_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+    if blocked {
+        return Err(format!("Refusing to open executable file: {path}"));
+    }
+
+    if !allowed.iter().any(|dir| resolved.starts_with(dir)) {
+        return Err(format!(
+            "Refusing to open path outside Coppy's folders: {path}"
+        ));
+    }
+
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A fresh `allowed` folder next to an `outside` one, under a temp dir
+    /// unique to the test.
+    fn layout(name: &str) -> (PathBuf, PathBuf) {
+        let root =
+            std::env::temp_dir().join(format!("coppy-open-guard-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let allowed = root.join("allowed");
+        let outside = root.join("outside");
+        fs::create_dir_all(&allowed).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(allowed.join("image.png"), b"png").unwrap();
+        fs::write(outside.join("secret.txt"), b"secret").unwrap();
+        fs::write(outside.join("run.exe"), b"MZ").unwrap();
+        (
+            allowed.canonicalize().unwrap(),
+            outside.canonicalize().unwrap(),
+        )
+    }
+
+    fn check_in(path: &Path, allowed: &Path) -> Result<PathBuf, String> {
+        check_within(path.to_str().unwrap(), &[allowed.to_path_buf()])
+    }
+
+    #[test]
+    fn file_inside_allowed_dir_is_opened() {
+        let (allowed, _) = layout("inside");
+        let path = allowed.join("image.png");
+        assert_eq!(check_in(&path, &allowed), Ok(path));
+    }
+
+    #[test]
+    fn dot_dot_cannot_climb_out() {
+        let (allowed, _) = layout("dotdot");
+        let path = allowed.join("..").join("outside").join("secret.txt");
+        assert_eq!(
+            check_in(&path, &allowed),
+            Err(format!(
+                "Refusing to open path outside Coppy's folders: {}",
+                path.display()
+            ))
+        );
+    }
+
+    #[test]
+    fn configured_save_directory_is_allowed() {
+        let (allowed, outside) = layout("save-dir");
+        let path = outside.join("secret.txt");
+        let dirs = with_output_dirs(vec![allowed.clone()], outside.to_str());
+        assert_eq!(
+            check_within(path.to_str().unwrap(), &dirs),
+            Ok(path.clone())
+        );
+
+        let dirs = with_output_dirs(vec![allowed], None);
+        assert!(check_within(path.to_str().unwrap(), &dirs).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_out_of_allowed_dir_is_refused() {
+        let (allowed, outside) = layout("symlink");
+        let link = allowed.join("link.txt");
+        std::os::unix::fs::symlink(outside.join("secret.txt"), &link).unwrap();
+        assert_eq!(
+            check_in(&link, &allowed),
+            Err(format!(
+                "Refusing to open path outside Coppy's folders: {}",
+                link.display()
+            ))
+        );
+    }
+
+    #[test]
+    fn executable_outside_allowed_dir_is_refused_as_executable() {
+        let (allowed, outside) = layout("exe");
+        let path = outside.join("run.exe");
+        assert_eq!(
+            check_in(&path, &allowed),
+            Err(format!(
+                "Refusing to open executable file: {}",
+                path.display()
+            ))
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tracing::warn;
+
+use crate::{history, profiles};
+
+const MAX_RECENT_PASTES: usize = 50;
+
+// Serializes read-modify-write cycles on recent_pastes.json.
+static RECENT_PASTES_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PasteRecord {
+    /// History or favorite id of the pasted item, when the caller knows it.
+    pub item_id: Option<String>,
+    pub ts: u64,
+    /// Title of the window the paste was sent to, where available.
+    pub target: Option<String>,
+}
+
+fn recent_pastes_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(profiles::profile_dir(app)?.join("recent_pastes.json"))
+}
+
+fn read(app: &AppHandle) -> Vec<PasteRecord> {
+    let path = match recent_pastes_file_path(app) {
+        Ok(path) if path.exists() => path,
+        _ => return Vec::new(),
+    };
+    fs::read_to_string(&path)
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+/// Records a successful paste in a small ring buffer, newest first.
+pub fn record(app: &AppHandle, item_id: Option<String>, target: Option<String>) {
+    let _guard = RECENT_PASTES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut recent = read(app);
+
+    recent.insert(
+        0,
+        PasteRecord {
+            item_id,
+            ts: history::now_ms(),
+            target,
+        },
+    );
+    recent.truncate(MAX_RECENT_PASTES);
+
+    let result = serde_json::to_string(&recent)
+        .map_err(|e| format!("Failed to serialize recent pastes: {e:?}"))
+        .and_then(|raw| {
+            // Temp file and rename, so a crash mid-write can't truncate it.
+            let path = recent_pastes_file_path(app)?;
+            let tmp = path.with_extension("json.tmp");
+            fs::write(&tmp, raw).map_err(|e| format!("Failed to write recent pastes: {e:?}"))?;
+            fs::rename(&tmp, &path).map_err(|e| format!("Failed to write recent pastes: {e:?}"))
+        });
+    if let Err(e) = result {
+        warn!("pastes: {e}");
+    }
+}
+
+pub fn recent(app: &AppHandle) -> Vec<PasteRecord> {
+    let _guard = RECENT_PASTES_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    read(app)
+}
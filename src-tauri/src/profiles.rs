@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tracing::warn;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+// Empty means "default"; set from profiles.json on startup.
+static ACTIVE_PROFILE: Mutex<String> = Mutex::new(String::new());
+
+#[derive(Serialize, Deserialize, Default)]
+struct ProfilesFile {
+    active: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ProfilesState {
+    pub active: String,
+    pub profiles: Vec<String>,
+}
+
+pub fn app_data_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {e:?}"))?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data dir: {e:?}"))?;
+    Ok(dir)
+}
+
+fn profiles_root(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_data_dir(app)?.join("profiles"))
+}
+
+fn profiles_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app_data_dir(app)?.join("profiles.json"))
+}
+
+fn validate_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Profile name cannot be empty".to_string());
+    }
+    // The name becomes a directory, so keep it to a portable character set.
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == ' ')
+    {
+        return Err(format!("Invalid profile name: {name}"));
+    }
+    Ok(name)
+}
+
+/// Restores the active profile saved by the previous session.
+pub fn init(app: &AppHandle) {
+    let active = profiles_file_path(app)
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|raw| serde_json::from_str::<ProfilesFile>(&raw).ok())
+        .and_then(|file| file.active)
+        .unwrap_or_default();
+
+    let exists = active.is_empty()
+        || active == DEFAULT_PROFILE
+        || profiles_root(app)
+            .map(|root| root.join(&active).is_dir())
+            .unwrap_or(false);
+
+    if exists {
+        set_active(active);
+    } else {
+        warn!("profiles: saved profile {active:?} no longer exists, using default");
+    }
+}
+
+fn set_active(name: String) {
+    let mut active = ACTIVE_PROFILE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    *active = name;
+}
+
+pub fn active() -> String {
+    let active = ACTIVE_PROFILE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if active.is_empty() {
+        DEFAULT_PROFILE.to_string()
+    } else {
+        active
+    }
+}
+
+/// Directory holding the active profile's data files. The default profile
+/// lives directly in the app data dir so data from before profiles existed
+/// keeps loading.
+pub fn profile_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let active = active();
+    if active == DEFAULT_PROFILE {
+        return app_data_dir(app);
+    }
+    let dir = profiles_root(app)?.join(active);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create profile dir: {e:?}"))?;
+    Ok(dir)
+}
+
+/// Counterpart of `profile_dir` under the machine-local app data dir, for data
+/// that must stay out of a roaming or cloud-synced data folder. On platforms
+/// where both dirs coincide, callers still keep such data in separate files.
+pub fn local_profile_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let root = app
+        .path()
+        .app_local_data_dir()
+        .map_err(|e| format!("Failed to get local app data dir: {e:?}"))?;
+    let active = active();
+    let dir = if active == DEFAULT_PROFILE {
+        root
+    } else {
+        root.join("profiles").join(active)
+    };
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create local data dir: {e:?}"))?;
+    Ok(dir)
+}
+
+pub fn list(app: &AppHandle) -> Result<ProfilesState, String> {
+    let mut profiles = vec![DEFAULT_PROFILE.to_string()];
+
+    let root = profiles_root(app)?;
+    if root.is_dir() {
+        let entries =
+            fs::read_dir(&root).map_err(|e| format!("Failed to read profiles dir: {e:?}"))?;
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| name != DEFAULT_PROFILE)
+            .collect();
+        names.sort();
+        profiles.extend(names);
+    }
+
+    Ok(ProfilesState {
+        active: active(),
+        profiles,
+    })
+}
+
+pub fn create(app: &AppHandle, name: &str) -> Result<(), String> {
+    let name = validate_name(name)?;
+    if name == DEFAULT_PROFILE {
+        return Err(format!("Profile already exists: {name}"));
+    }
+
+    let dir = profiles_root(app)?.join(name);
+    if dir.exists() {
+        return Err(format!("Profile already exists: {name}"));
+    }
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create profile dir: {e:?}"))
+}
+
+pub fn switch(app: &AppHandle, name: &str) -> Result<(), String> {
+    let name = validate_name(name)?;
+    if name != DEFAULT_PROFILE && !profiles_root(app)?.join(name).is_dir() {
+        return Err(format!("Profile does not exist: {name}"));
+    }
+
+    let raw = serde_json::to_string(&ProfilesFile {
+        active: Some(name.to_string()),
+    })
+    .map_err(|e| format!("Failed to serialize profiles: {e:?}"))?;
+    fs::write(profiles_file_path(app)?, raw)
+        .map_err(|e| format!("Failed to write profiles: {e:?}"))?;
+
+    set_active(name.to_string());
+
+    let _ = app.emit("profile-changed", name.to_string());
+    Ok(())
+}
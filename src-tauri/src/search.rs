@@ -0,0 +1,132 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::{favorites, history};
+
+const MAX_RESULTS: usize = 100;
+
+// Only the start of long items is matched; skim's matcher is quadratic in
+// the worst case.
+const MAX_MATCH_CHARS: usize = 4096;
+
+#[derive(Serialize, Clone)]
+pub struct ScoredItem {
+    /// `"favorite"` or `"history"`.
+    pub source: &'static str,
+    pub id: String,
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub timestamp: u64,
+    pub score: i64,
+    /// `"name"` or `"content"`, whichever matched best.
+    pub field: &'static str,
+    /// Char (not byte) offsets of the matched letters within `field`.
+    pub indices: Vec<usize>,
+}
+
+struct Candidate {
+    source: &'static str,
+    id: String,
+    item_type: String,
+    content: String,
+    name: Option<String>,
+    timestamp: u64,
+}
+
+fn best_match(
+    matcher: &SkimMatcherV2,
+    candidate: &Candidate,
+    query: &str,
+) -> Option<(i64, &'static str, Vec<usize>)> {
+    let name = candidate
+        .name
+        .as_deref()
+        .and_then(|name| matcher.fuzzy_indices(name, query))
+        .map(|(score, indices)| (score, "name", indices));
+    // Image content is a data URL, so only their names are searched.
+    let content = (candidate.item_type != "image")
+        .then(|| {
+            let end = candidate
+                .content
+                .char_indices()
+                .nth(MAX_MATCH_CHARS)
+                .map_or(candidate.content.len(), |(i, _)| i);
+            matcher.fuzzy_indices(&candidate.content[..end], query)
+        })
+        .flatten()
+        .map(|(score, indices)| (score, "content", indices));
+    match (name, content) {
+        (Some(name), Some(content)) => Some(if content.0 > name.0 { content } else { name }),
+        (name, content) => name.or(content),
+    }
+}
+
+/// Typo-tolerant search over favorites and/or history (`scope` is `"all"`,
+/// `"favorites"` or `"history"`), best match first and newest first among
+/// equal scores.
+pub fn fuzzy(app: &AppHandle, query: &str, scope: &str) -> Result<Vec<ScoredItem>, String> {
+    let (favorites, history) = match scope {
+        "all" => (true, true),
+        "favorites" => (true, false),
+        "history" => (false, true),
+        other => return Err(format!("Unknown search scope: {other}")),
+    };
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut candidates = Vec::new();
+    if favorites {
+        candidates.extend(
+            favorites::load(app)?
+                .items
+                .into_iter()
+                .map(|item| Candidate {
+                    source: "favorite",
+                    id: item.id,
+                    item_type: item.item_type,
+                    content: item.content,
+                    name: item.name,
+                    timestamp: item.timestamp,
+                }),
+        );
+    }
+    if history {
+        candidates.extend(history::load(app)?.into_iter().map(|item| Candidate {
+            source: "history",
+            id: item.id,
+            item_type: item.item_type,
+            content: item.content,
+            name: None,
+            timestamp: item.timestamp,
+        }));
+    }
+
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut results: Vec<ScoredItem> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let (score, field, indices) = best_match(&matcher, &candidate, query)?;
+            Some(ScoredItem {
+                source: candidate.source,
+                id: candidate.id,
+                item_type: candidate.item_type,
+                content: candidate.content,
+                name: candidate.name,
+                timestamp: candidate.timestamp,
+                score,
+                field,
+                indices,
+            })
+        })
+        .collect();
+    results.sort_by(|a, b| b.score.cmp(&a.score).then(b.timestamp.cmp(&a.timestamp)));
+    results.truncate(MAX_RESULTS);
+    Ok(results)
+}
@@ -0,0 +1,237 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tracing::warn;
+
+use crate::window::WindowGeometry;
+use crate::{filenames, profiles};
+
+/// What happens to the system clipboard once a paste has been sent.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PostPasteClipboard {
+    /// Leave the pasted content on the clipboard.
+    #[default]
+    Keep,
+    /// Empty the clipboard so the pasted content doesn't linger.
+    Clear,
+    /// Put back whatever was on the clipboard before the paste. Content
+    /// that can't be put back (e.g. copied files) is left as pasted rather
+    /// than cleared.
+    Restore,
+}
+
+impl PostPasteClipboard {
+    pub fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "keep" => Ok(Self::Keep),
+            "clear" => Ok(Self::Clear),
+            "restore" => Ok(Self::Restore),
+            other => Err(format!("Unknown post-paste clipboard mode: {other}")),
+        }
+    }
+}
+
+/// How a re-copy of content identical to the last capture is recorded.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum RecopyBehavior {
+    /// Treat it as no change.
+    #[default]
+    Ignore,
+    /// Bump the existing history entry to the top.
+    MoveToTop,
+    /// Record it as a separate history entry.
+    NewEntry,
+}
+
+impl RecopyBehavior {
+    pub fn parse(behavior: &str) -> Result<Self, String> {
+        match behavior {
+            "ignore" => Ok(Self::Ignore),
+            "move-to-top" => Ok(Self::MoveToTop),
+            "new-entry" => Ok(Self::NewEntry),
+            other => Err(format!("Unknown re-copy behavior: {other}")),
+        }
+    }
+}
+
+/// Modifier whose double tap summons the window (Windows only).
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DoubleTapKey {
+    #[default]
+    Ctrl,
+    Shift,
+    Alt,
+    Win,
+}
+
+impl DoubleTapKey {
+    pub fn parse(key: &str) -> Result<Self, String> {
+        match key {
+            "ctrl" => Ok(Self::Ctrl),
+            "shift" => Ok(Self::Shift),
+            "alt" => Ok(Self::Alt),
+            "win" => Ok(Self::Win),
+            other => Err(format!("Unknown double-tap key: {other}")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub post_paste_clipboard: PostPasteClipboard,
+    pub recopy_behavior: RecopyBehavior,
+    /// Put the newest history item back on the clipboard at launch.
+    pub restore_last_on_startup: bool,
+    /// Record what is already on the clipboard at launch. When off, it only
+    /// becomes the baseline for change detection.
+    pub capture_on_startup: bool,
+    /// Skip image capture and keep a much shorter history.
+    pub low_memory_mode: bool,
+    /// Oldest history entries are evicted past this count.
+    pub max_history_items: usize,
+    /// Total size of image entries kept in history; 0 means no limit.
+    pub max_history_image_bytes: u64,
+    pub log_level: String,
+    /// Lets `open_path`/`reveal_in_explorer` open anything, bypassing the
+    /// folder allowlist.
+    pub allow_arbitrary_open: bool,
+    /// Global shortcut that shows/hides the main window.
+    pub toggle_shortcut: String,
+    /// Max gap between the two taps of the Windows double-Ctrl trigger;
+    /// 0 turns the trigger off.
+    pub double_ctrl_threshold_ms: u64,
+    pub double_tap_key: DoubleTapKey,
+    /// Where saved images go instead of the Explorer/Downloads default.
+    pub save_directory: Option<String>,
+    /// Name for saved files; see `filenames::unique_path` for the tokens.
+    pub filename_pattern: String,
+    /// Executables whose copies are never recorded, matched
+    /// case-insensitively with or without ".exe".
+    pub ignored_apps: Vec<String>,
+    /// Copies from ignored apps are kept this long instead of being
+    /// dropped, and never synced; 0 drops them.
+    pub ignored_app_expiry_ms: u64,
+    /// Normalize line endings and strip trailing whitespace of captured text.
+    pub trim_captured_text: bool,
+    /// Longest side of the image previews sent to the UI; 0 sends full
+    /// resolution.
+    pub image_preview_max_px: u32,
+    /// Captured text longer than this many characters is kept whole on disk
+    /// and shown cut short; 0 means no limit.
+    pub max_text_length: usize,
+    /// How often a clipboard write is tried before giving up, e.g. while
+    /// another clipboard manager holds the clipboard open.
+    pub clipboard_retry_attempts: u32,
+    /// Wait before the second try; it doubles after each failure.
+    pub clipboard_retry_delay_ms: u64,
+    /// Global CmdOrCtrl+Alt+1-9 shortcuts that paste history slots 1-9.
+    pub quick_paste_shortcuts: bool,
+    /// Size (and pinned position) the main window reopens with.
+    pub window_geometry: Option<WindowGeometry>,
+}
+
+pub const DEFAULT_TOGGLE_SHORTCUT: &str = "CmdOrCtrl+Shift+V";
+
+/// Password managers, which put secrets on the clipboard.
+pub const DEFAULT_IGNORED_APPS: &[&str] = &[
+    "1Password",
+    "Bitwarden",
+    "Dashlane",
+    "Enpass",
+    "KeePass",
+    "KeePassXC",
+    "LastPass",
+    "RoboForm",
+];
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            post_paste_clipboard: PostPasteClipboard::default(),
+            recopy_behavior: RecopyBehavior::default(),
+            restore_last_on_startup: false,
+            capture_on_startup: true,
+            low_memory_mode: false,
+            max_history_items: 200,
+            max_history_image_bytes: 50 * 1024 * 1024,
+            log_level: "info".to_string(),
+            allow_arbitrary_open: false,
+            toggle_shortcut: DEFAULT_TOGGLE_SHORTCUT.to_string(),
+            double_ctrl_threshold_ms: 400,
+            double_tap_key: DoubleTapKey::default(),
+            save_directory: None,
+            filename_pattern: filenames::DEFAULT_PATTERN.to_string(),
+            ignored_apps: DEFAULT_IGNORED_APPS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            ignored_app_expiry_ms: 0,
+            trim_captured_text: false,
+            image_preview_max_px: 512,
+            max_text_length: 100_000,
+            clipboard_retry_attempts: 8,
+            clipboard_retry_delay_ms: 10,
+            quick_paste_shortcuts: false,
+            window_geometry: None,
+        }
+    }
+}
+
+// None until `init` runs; readers fall back to defaults.
+static SETTINGS: Mutex<Option<Settings>> = Mutex::new(None);
+
+fn settings_file_path(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(profiles::app_data_dir(app)?.join("settings.json"))
+}
+
+pub fn init(app: &AppHandle) {
+    let loaded = match settings_file_path(app) {
+        Ok(path) if path.exists() => fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read settings: {e:?}"))
+            .and_then(|raw| {
+                serde_json::from_str(&raw).map_err(|e| format!("Failed to parse settings: {e:?}"))
+            }),
+        Ok(_) => Ok(Settings::default()),
+        Err(e) => Err(e),
+    };
+
+    let settings = loaded.unwrap_or_else(|e| {
+        warn!("settings: {e}, using defaults");
+        Settings::default()
+    });
+    *SETTINGS.lock().unwrap_or_else(PoisonError::into_inner) = Some(settings);
+}
+
+pub fn get() -> Settings {
+    SETTINGS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Applies `f` to the current settings and writes the result to disk. The
+/// change only takes effect once it's written. The file is replaced via a
+/// temp file and rename, so a crash mid-write can't leave a truncated one.
+pub fn update(app: &AppHandle, f: impl FnOnce(&mut Settings)) -> Result<(), String> {
+    let mut guard = SETTINGS.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut settings = guard.clone().unwrap_or_default();
+    f(&mut settings);
+
+    let raw = serde_json::to_string(&settings)
+        .map_err(|e| format!("Failed to serialize settings: {e:?}"))?;
+    let path = settings_file_path(app)?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, raw).map_err(|e| format!("Failed to write settings: {e:?}"))?;
+    fs::rename(&tmp, &path).map_err(|e| format!("Failed to write settings: {e:?}"))?;
+
+    *guard = Some(settings);
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use std::sync::{Mutex, PoisonError};
+
+use arboard::Clipboard;
+
+/// Texts collected for a combined paste, oldest first. Kept in memory only.
+static STACK: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Adds the text currently on the clipboard. Returns the stack.
+pub fn push_clipboard() -> Result<Vec<String>, String> {
+    let text = Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|e| format!("Failed to read clipboard text: {e:?}"))?;
+    if text.is_empty() {
+        return Err("The clipboard holds no text".to_string());
+    }
+    let mut stack = STACK.lock().unwrap_or_else(PoisonError::into_inner);
+    stack.push(text);
+    Ok(stack.clone())
+}
+
+pub fn get() -> Vec<String> {
+    STACK.lock().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+pub fn clear() {
+    STACK.lock().unwrap_or_else(PoisonError::into_inner).clear();
+}
+
+/// The collected texts joined with `separator`, or `None` if there are none.
+pub fn joined(separator: &str) -> Option<String> {
+    let stack = STACK.lock().unwrap_or_else(PoisonError::into_inner);
+    (!stack.is_empty()).then(|| stack.join(separator))
+}
@@ -0,0 +1,50 @@
+use std::sync::{Mutex, PoisonError};
+
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::{clipboard_listener, history};
+
+static LAST_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+/// Remembers `message` as the most recent error, for `collect`.
+pub fn record_error(message: &str) {
+    *LAST_ERROR.lock().unwrap_or_else(PoisonError::into_inner) = Some(message.to_string());
+}
+
+#[derive(Serialize)]
+pub struct AppStatus {
+    /// Whether the clipboard watcher is running, paused or not.
+    pub monitoring: bool,
+    pub paused: bool,
+    /// Whether changes arrive as Windows clipboard events rather than by
+    /// polling every `poll_interval_ms`.
+    pub event_listener: bool,
+    pub poll_interval_ms: u64,
+    /// The Windows double-tap hook; always false elsewhere.
+    pub keyboard_hook_installed: bool,
+    /// `None` when history can't be read; `last_error` then says why.
+    pub history_items: Option<usize>,
+    pub last_error: Option<String>,
+}
+
+/// Snapshot of the background machinery, for the settings page and bug
+/// reports.
+pub fn collect(app: &AppHandle) -> AppStatus {
+    let history_items = history::load(app)
+        .map(|items| items.len())
+        .inspect_err(|e| record_error(e))
+        .ok();
+    AppStatus {
+        monitoring: clipboard_listener::is_running(),
+        paused: clipboard_listener::is_paused(),
+        event_listener: clipboard_listener::is_event_driven(),
+        poll_interval_ms: clipboard_listener::poll_interval_ms(),
+        keyboard_hook_installed: crate::keyboard_hook_active(),
+        history_items,
+        last_error: LAST_ERROR
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone(),
+    }
+}
@@ -0,0 +1,538 @@
+use base64::{engine::general_purpose, Engine as _};
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use time::OffsetDateTime;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Serialize, Clone)]
+pub struct DiffLine {
+    /// "added", "removed" or "unchanged".
+    pub kind: &'static str,
+    pub content: String,
+    /// 1-based line number in the first text, if the line exists there.
+    pub old_line: Option<usize>,
+    /// 1-based line number in the second text, if the line exists there.
+    pub new_line: Option<usize>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct TextStats {
+    /// Grapheme clusters, so an emoji or an accented letter counts once.
+    pub chars: usize,
+    pub chars_no_whitespace: usize,
+    pub words: usize,
+    /// CRLF and LF both end a line; a trailing newline doesn't start another.
+    pub lines: usize,
+}
+
+pub fn stats(text: &str) -> TextStats {
+    let graphemes: Vec<&str> = text.graphemes(true).collect();
+    TextStats {
+        chars: graphemes.len(),
+        chars_no_whitespace: graphemes
+            .iter()
+            .filter(|g| !g.chars().all(char::is_whitespace))
+            .count(),
+        words: text.unicode_words().count(),
+        lines: text.lines().count(),
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct ExpandedSnippet {
+    pub text: String,
+    /// Byte offset in `text` where the first `{cursor}` was.
+    pub cursor: Option<usize>,
+}
+
+/// Fills in the tokens of a snippet: `{date}` (YYYY-MM-DD), `{time}`
+/// (HH:MM), `{clipboard}` and `{cursor}`, which is dropped and its position
+/// reported. A single pass, so tokens inside the clipboard text stay as
+/// they are.
+pub fn expand_snippet(text: &str, clipboard: &str, now: OffsetDateTime) -> ExpandedSnippet {
+    let date = format!(
+        "{:04}-{:02}-{:02}",
+        now.year(),
+        now.month() as u8,
+        now.day()
+    );
+    let time = format!("{:02}:{:02}", now.hour(), now.minute());
+
+    let mut out = String::with_capacity(text.len());
+    let mut cursor = None;
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let token = ["{date}", "{time}", "{clipboard}", "{cursor}"]
+            .into_iter()
+            .find(|token| rest.starts_with(token));
+        match token {
+            Some("{date}") => out.push_str(&date),
+            Some("{time}") => out.push_str(&time),
+            Some("{clipboard}") => out.push_str(clipboard),
+            Some(_) => {
+                cursor.get_or_insert(out.len());
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+                continue;
+            }
+        }
+        rest = &rest[token.map_or(0, str::len)..];
+    }
+    out.push_str(rest);
+    ExpandedSnippet { text: out, cursor }
+}
+
+/// Converts CRLF/CR line endings to LF, strips trailing whitespace from every
+/// line and drops blank lines at either end. Leading indentation is kept so
+/// copied code survives.
+pub fn normalize_whitespace(text: &str) -> String {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let start = lines
+        .iter()
+        .position(|line| !line.is_empty())
+        .unwrap_or(lines.len());
+    let end = lines
+        .iter()
+        .rposition(|line| !line.is_empty())
+        .map_or(start, |last| last + 1);
+    lines[start..end].join("\n")
+}
+
+// Longer text that contains a color is a document, not a swatch.
+const MAX_COLOR_LEN: usize = 32;
+
+/// Recognizes a copied CSS color (`#rgb`, `#rrggbb`, `#rrggbbaa`, `rgb()` or
+/// `rgba()`) and returns it as lowercase `#rrggbb`, or `#rrggbbaa` when it
+/// isn't opaque. `#rgb` and `#rrggbb` made only of decimal digits read as
+/// issue references (`#123`) and are left alone.
+pub fn parse_color(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.len() > MAX_COLOR_LEN || text.contains('\n') {
+        return None;
+    }
+    let text = text.to_lowercase();
+    let (r, g, b, a) = match text.strip_prefix('#') {
+        Some(hex) => parse_hex_color(hex)?,
+        None => parse_rgb_function(&text)?,
+    };
+    Some(if a == 255 {
+        format!("#{r:02x}{g:02x}{b:02x}")
+    } else {
+        format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+    })
+}
+
+fn parse_hex_color(hex: &str) -> Option<(u8, u8, u8, u8)> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    if matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    match hex.len() {
+        3 => {
+            let digits: Vec<u8> = hex
+                .chars()
+                .filter_map(|c| c.to_digit(16))
+                .map(|d| d as u8 * 17)
+                .collect();
+            Some((digits[0], digits[1], digits[2], 255))
+        }
+        6 => Some((channel(0)?, channel(2)?, channel(4)?, 255)),
+        8 => Some((channel(0)?, channel(2)?, channel(4)?, channel(6)?)),
+        _ => None,
+    }
+}
+
+fn parse_rgb_function(text: &str) -> Option<(u8, u8, u8, u8)> {
+    let args = text
+        .strip_prefix("rgba(")
+        .or_else(|| text.strip_prefix("rgb("))?
+        .strip_suffix(')')?;
+    let parts: Vec<&str> = args.split(',').map(str::trim).collect();
+    let channel = |part: &str| -> Option<u8> {
+        match part.strip_suffix('%') {
+            Some(percent) => unit_to_byte(percent.parse::<f32>().ok()? / 100.0),
+            None => part.parse().ok(),
+        }
+    };
+    let alpha = |part: &str| -> Option<u8> {
+        match part.strip_suffix('%') {
+            Some(percent) => unit_to_byte(percent.parse::<f32>().ok()? / 100.0),
+            None => unit_to_byte(part.parse().ok()?),
+        }
+    };
+    match parts.as_slice() {
+        [r, g, b] => Some((channel(r)?, channel(g)?, channel(b)?, 255)),
+        [r, g, b, a] => Some((channel(r)?, channel(g)?, channel(b)?, alpha(a)?)),
+        _ => None,
+    }
+}
+
+/// Maps 0.0-1.0 to 0-255, rejecting anything outside that range.
+fn unit_to_byte(value: f32) -> Option<u8> {
+    (0.0..=1.0)
+        .contains(&value)
+        .then(|| (value * 255.0).round() as u8)
+}
+
+/// Re-indents JSON with two spaces.
+pub fn format_json(text: &str) -> Result<String, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {e}"))?;
+    serde_json::to_string_pretty(&value).map_err(|e| format!("Failed to format JSON: {e:?}"))
+}
+
+/// Only objects and arrays count, so a bare number or quoted word stays text.
+pub fn is_json(text: &str) -> bool {
+    let trimmed = text.trim();
+    (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde::de::IgnoredAny>(trimmed).is_ok()
+}
+
+// Tell-tale substrings per language, keyed by the identifiers common
+// highlighters (highlight.js, Prism) use. Each marker counts once, however
+// often it appears.
+const LANGUAGE_MARKERS: &[(&str, &[&str])] = &[
+    (
+        "rust",
+        &[
+            "fn ",
+            "let mut ",
+            "impl ",
+            "pub fn ",
+            "-> ",
+            "&self",
+            "&mut ",
+            "use std::",
+            "println!",
+            "#[derive",
+            "Option<",
+            "Result<",
+            ".unwrap()",
+            "::new(",
+        ],
+    ),
+    (
+        "python",
+        &[
+            "def ", "elif ", "self.", "print(", "__init__", "lambda ", "import ", "None", "True",
+            "False", " is not ", "):\n",
+        ],
+    ),
+    (
+        "javascript",
+        &[
+            "const ",
+            "let ",
+            "function ",
+            "=> ",
+            "console.log",
+            "document.",
+            "require(",
+            "export ",
+            "===",
+            "!==",
+            "undefined",
+        ],
+    ),
+    (
+        "java",
+        &[
+            "public class ",
+            "public static void",
+            "System.out.",
+            "private ",
+            "import java.",
+            "@Override",
+            "extends ",
+            "implements ",
+            "String[] ",
+        ],
+    ),
+    (
+        "csharp",
+        &[
+            "using System",
+            "namespace ",
+            "Console.Write",
+            "public class ",
+            "{ get; set; }",
+            "var ",
+            "async Task",
+            "string[] ",
+        ],
+    ),
+    (
+        "go",
+        &[
+            "package ",
+            "func ",
+            ":= ",
+            "fmt.",
+            "import (",
+            "go func",
+            "chan ",
+            "defer ",
+            "err != nil",
+        ],
+    ),
+    (
+        "cpp",
+        &[
+            "#include",
+            "std::",
+            "cout <<",
+            "nullptr",
+            "template<",
+            "template <",
+            "namespace ",
+            "int main(",
+        ],
+    ),
+    (
+        "c",
+        &[
+            "#include",
+            "printf(",
+            "malloc(",
+            "int main(",
+            "NULL",
+            "->",
+            "sizeof(",
+        ],
+    ),
+    (
+        "sql",
+        &[
+            "SELECT ",
+            " FROM ",
+            "WHERE ",
+            "INSERT INTO",
+            "UPDATE ",
+            "CREATE TABLE",
+            "JOIN ",
+            "GROUP BY",
+            "ORDER BY",
+            "VALUES",
+        ],
+    ),
+    (
+        "html",
+        &[
+            "<div",
+            "</",
+            "<html",
+            "<!DOCTYPE",
+            "<span",
+            "<p>",
+            "class=\"",
+            "href=\"",
+        ],
+    ),
+    (
+        "css",
+        &[
+            "px;",
+            "color:",
+            "margin:",
+            "padding:",
+            "display:",
+            "font-",
+            "@media",
+            "background:",
+        ],
+    ),
+    (
+        "bash",
+        &[
+            "#!/bin/", "echo ", "$(", "sudo ", "| grep", "; then", "fi\n", "; do", "done\n",
+        ],
+    ),
+    (
+        "php",
+        &["<?php", "$this->", "echo ", "function ", "=> $", "->"],
+    ),
+    (
+        "ruby",
+        &[
+            "def ",
+            "end\n",
+            "puts ",
+            "require '",
+            "do |",
+            ".each ",
+            "attr_accessor",
+        ],
+    ),
+];
+
+// TypeScript is JavaScript plus these; any of them turns a JavaScript guess
+// into TypeScript.
+const TYPESCRIPT_MARKERS: &[&str] = &[
+    ": string",
+    ": number",
+    ": boolean",
+    "interface ",
+    "export type ",
+    "<T>",
+    " as const",
+];
+
+const MIN_LANGUAGE_MARKERS: usize = 2;
+
+// Code is dense in these; prose barely uses them.
+const CODE_PUNCTUATION: &[char] = &[
+    '{', '}', '(', ')', ';', '=', '<', '>', '[', ']', '$', '#', ':',
+];
+
+// Only the start of long text is looked at.
+const MAX_DETECT_BYTES: usize = 4096;
+
+/// Guesses the programming language of a snippet from tell-tale keywords
+/// and punctuation. Errs on the side of `None`: text with little code
+/// punctuation, too few markers or a tie between languages gets no guess.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let mut end = text.len().min(MAX_DETECT_BYTES);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let text = text[..end].trim();
+
+    if let Some(interpreter) = text.strip_prefix("#!").and_then(|line| line.lines().next()) {
+        let interpreter = interpreter.rsplit(['/', ' ']).next().unwrap_or("");
+        match interpreter {
+            "bash" | "sh" | "zsh" => return Some("bash"),
+            "python" | "python3" => return Some("python"),
+            "node" => return Some("javascript"),
+            _ => {}
+        }
+    }
+    if is_json(text) {
+        return Some("json");
+    }
+
+    let visible = text.chars().filter(|c| !c.is_whitespace()).count();
+    let punctuation = text
+        .chars()
+        .filter(|c| CODE_PUNCTUATION.contains(c))
+        .count();
+    if visible == 0 || punctuation * 25 < visible {
+        return None;
+    }
+
+    let mut scores: Vec<(&'static str, usize)> = LANGUAGE_MARKERS
+        .iter()
+        .map(|(language, markers)| {
+            let hits = markers
+                .iter()
+                .filter(|marker| text.contains(*marker))
+                .count();
+            (*language, hits)
+        })
+        .collect();
+    scores.sort_by_key(|&(_, hits)| std::cmp::Reverse(hits));
+    let (language, hits) = scores[0];
+    if hits < MIN_LANGUAGE_MARKERS || scores[1].1 == hits {
+        return None;
+    }
+    if language == "javascript"
+        && TYPESCRIPT_MARKERS
+            .iter()
+            .any(|marker| text.contains(marker))
+    {
+        return Some("typescript");
+    }
+    Some(language)
+}
+
+/// Applies one of the "transform" actions offered on text items.
+pub fn transform(text: &str, op: &str) -> Result<String, String> {
+    Ok(match op {
+        "uppercase" => text.to_uppercase(),
+        "lowercase" => text.to_lowercase(),
+        "trim" => text.trim().to_string(),
+        "collapse_whitespace" => text.split_whitespace().collect::<Vec<_>>().join(" "),
+        "reverse" => text.chars().rev().collect(),
+        "base64_encode" => general_purpose::STANDARD.encode(text),
+        "base64_decode" => {
+            let bytes = general_purpose::STANDARD
+                .decode(text.trim())
+                .map_err(|e| format!("Invalid base64: {e}"))?;
+            String::from_utf8(bytes).map_err(|_| "Decoded base64 is not valid UTF-8".to_string())?
+        }
+        "url_encode" => urlencoding::encode(text).into_owned(),
+        "url_decode" => urlencoding::decode(text)
+            .map_err(|_| "Decoded URL text is not valid UTF-8".to_string())?
+            .into_owned(),
+        other => return Err(format!("Unknown transform: {other}")),
+    })
+}
+
+/// Line-based diff of `old` against `new`.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            kind: match change.tag() {
+                ChangeTag::Insert => "added",
+                ChangeTag::Delete => "removed",
+                ChangeTag::Equal => "unchanged",
+            },
+            content: change.value().trim_end_matches(['\r', '\n']).to_string(),
+            old_line: change.old_index().map(|i| i + 1),
+            new_line: change.new_index().map(|i| i + 1),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crlf_and_lf_count_the_same_lines() {
+        assert_eq!(stats("one\ntwo\nthree").lines, 3);
+        assert_eq!(stats("one\r\ntwo\r\nthree").lines, 3);
+        // A trailing newline ends the last line rather than starting one.
+        assert_eq!(stats("one\r\ntwo\r\n").lines, 2);
+        assert_eq!(stats("one\ntwo\n").lines, 2);
+    }
+
+    #[test]
+    fn emoji_and_combining_sequences_count_once() {
+        // Family emoji joined with ZWJs, and a flag made of two regional
+        // indicators.
+        let s = stats("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{1F1EB}\u{1F1F7}");
+        assert_eq!(s.chars, 2);
+        // "e" followed by a combining acute accent.
+        let s = stats("cafe\u{301}");
+        assert_eq!(s.chars, 4);
+        assert_eq!(s.chars_no_whitespace, 4);
+        assert_eq!(s.words, 1);
+    }
+
+    #[test]
+    fn whitespace_only_has_no_words() {
+        let s = stats(" \t\r\n ");
+        assert_eq!(s.chars, 4);
+        assert_eq!(s.chars_no_whitespace, 0);
+        assert_eq!(s.words, 0);
+        assert_eq!(s.lines, 2);
+    }
+
+    #[test]
+    fn issue_refs_are_not_colors() {
+        assert_eq!(parse_color("#123"), None);
+        assert_eq!(parse_color("#100200"), None);
+        assert_eq!(parse_color("#12a"), Some("#1122aa".to_string()));
+        assert_eq!(parse_color("#10020A"), Some("#10020a".to_string()));
+        assert_eq!(parse_color("rgb(16, 2, 0)"), Some("#100200".to_string()));
+    }
+}
@@ -0,0 +1,114 @@
+use tauri::menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager, PhysicalPosition, Wry};
+use tracing::warn;
+
+use crate::{clipboard_listener, history, hotkey};
+
+/// Check items whose state can also change outside the menu; see `sync`.
+struct TrayMenu {
+    pause: CheckMenuItem<Wry>,
+    autostart: CheckMenuItem<Wry>,
+}
+
+pub fn init(app: &AppHandle) -> Result<(), String> {
+    let menu_error = |e: tauri::Error| format!("Failed to build tray menu: {e:?}");
+
+    let toggle =
+        MenuItem::with_id(app, "toggle", "Show/Hide", true, None::<&str>).map_err(menu_error)?;
+    let pause = CheckMenuItem::with_id(
+        app,
+        "pause",
+        "Pause monitoring",
+        true,
+        clipboard_listener::is_paused(),
+        None::<&str>,
+    )
+    .map_err(menu_error)?;
+    let clear =
+        MenuItem::with_id(app, "clear", "Clear history", true, None::<&str>).map_err(menu_error)?;
+    let autostart = CheckMenuItem::with_id(
+        app,
+        "autostart",
+        "Start at login",
+        true,
+        crate::autostart_is_enabled(app.clone()).unwrap_or(false),
+        None::<&str>,
+    )
+    .map_err(menu_error)?;
+    let separator = PredefinedMenuItem::separator(app).map_err(menu_error)?;
+    let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>).map_err(menu_error)?;
+    let menu = Menu::with_items(
+        app,
+        &[&toggle, &pause, &clear, &autostart, &separator, &quit],
+    )
+    .map_err(menu_error)?;
+
+    let mut builder = TrayIconBuilder::with_id("main")
+        .tooltip("Coppy")
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event(|app, event| on_menu_event(app, event.id().as_ref()))
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                position,
+                ..
+            } = event
+            {
+                let cursor = PhysicalPosition::new(position.x as i32, position.y as i32);
+                hotkey::toggle_main_window(tray.app_handle(), Some(cursor));
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder
+        .build(app)
+        .map_err(|e| format!("Failed to create tray icon: {e:?}"))?;
+
+    app.manage(TrayMenu { pause, autostart });
+    Ok(())
+}
+
+fn on_menu_event(app: &AppHandle, id: &str) {
+    let result = match id {
+        "toggle" => {
+            hotkey::toggle_main_window(app, None);
+            Ok(())
+        }
+        "pause" => {
+            clipboard_listener::set_paused(!clipboard_listener::is_paused());
+            Ok(())
+        }
+        "clear" => history::clear(app),
+        "autostart" => match crate::autostart_is_enabled(app.clone()) {
+            Ok(true) => crate::autostart_disable(app.clone()),
+            Ok(false) => crate::autostart_enable(app.clone()),
+            Err(e) => Err(e),
+        },
+        "quit" => {
+            app.exit(0);
+            Ok(())
+        }
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        warn!("tray: {e}");
+    }
+    sync(app);
+}
+
+/// Brings the check marks in line with the current state. Check items flip
+/// themselves when clicked, and the same settings can change through
+/// commands, so this runs after either.
+pub fn sync(app: &AppHandle) {
+    let Some(menu) = app.try_state::<TrayMenu>() else {
+        return;
+    };
+    let _ = menu.pause.set_checked(clipboard_listener::is_paused());
+    if let Ok(enabled) = crate::autostart_is_enabled(app.clone()) {
+        let _ = menu.autostart.set_checked(enabled);
+    }
+}
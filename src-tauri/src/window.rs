@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, PhysicalPosition, PhysicalSize, WebviewWindow};
+
+use crate::settings;
+
+/// Saved size of the main window, in physical pixels.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct WindowGeometry {
+    pub width: u32,
+    pub height: u32,
+    /// Where the user pinned the window; when unset it opens at the cursor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<(i32, i32)>,
+}
+
+/// Saves the main window's current size and, with `remember_position`, its
+/// position, so it reopens that way.
+pub fn save_geometry(app: &AppHandle, remember_position: bool) -> Result<(), String> {
+    let window = app
+        .get_webview_window("main")
+        .ok_or_else(|| "Main window not found".to_string())?;
+    let size = window
+        .inner_size()
+        .map_err(|e| format!("Failed to read window size: {e:?}"))?;
+    let position = if remember_position {
+        let position = window
+            .outer_position()
+            .map_err(|e| format!("Failed to read window position: {e:?}"))?;
+        Some((position.x, position.y))
+    } else {
+        None
+    };
+
+    settings::update(app, |s| {
+        s.window_geometry = Some(WindowGeometry {
+            width: size.width,
+            height: size.height,
+            position,
+        })
+    })
+}
+
+/// Shifts `position` so the whole window fits in the work area of the
+/// monitor under `anchor` (or the primary monitor, if none is), keeping it
+/// off the taskbar and screen edges.
+fn clamp_to_work_area(
+    window: &WebviewWindow,
+    position: PhysicalPosition<i32>,
+    anchor: PhysicalPosition<i32>,
+) -> PhysicalPosition<i32> {
+    let monitor = window
+        .monitor_from_point(anchor.x as f64, anchor.y as f64)
+        .ok()
+        .flatten()
+        .or_else(|| window.primary_monitor().ok().flatten());
+    let (Some(monitor), Ok(size)) = (monitor, window.outer_size()) else {
+        return position;
+    };
+
+    // The window is resized by the monitor's DPI when it lands on a monitor
+    // with another scale factor.
+    let ratio = monitor.scale_factor() / window.scale_factor().unwrap_or(1.0);
+    let width = (size.width as f64 * ratio).round() as i32;
+    let height = (size.height as f64 * ratio).round() as i32;
+
+    let area = monitor.work_area();
+    let (left, top) = (area.position.x, area.position.y);
+    let right = left + area.size.width as i32 - width;
+    let bottom = top + area.size.height as i32 - height;
+    // A window larger than the work area sticks to its top-left corner.
+    PhysicalPosition::new(
+        position.x.min(right).max(left),
+        position.y.min(bottom).max(top),
+    )
+}
+
+/// Applies the saved size before showing the window, then moves it to the
+/// saved position or, without one, just above `cursor` if given. Either way
+/// it's kept within the work area of the monitor it lands on.
+pub fn place(window: &WebviewWindow, cursor: Option<PhysicalPosition<i32>>) {
+    let geometry = settings::get().window_geometry;
+    if let Some(geometry) = geometry {
+        let _ = window.set_size(PhysicalSize::new(geometry.width, geometry.height));
+    }
+
+    let (position, anchor) = match (geometry.and_then(|g| g.position), cursor) {
+        (Some((x, y)), _) => {
+            let position = PhysicalPosition::new(x, y);
+            (position, position)
+        }
+        (None, Some(cursor)) => {
+            let height = window
+                .outer_size()
+                .map(|size| size.height as i32)
+                .unwrap_or(0);
+            (PhysicalPosition::new(cursor.x, cursor.y - height), cursor)
+        }
+        (None, None) => return,
+    };
+    let _ = window.set_position(clamp_to_work_area(window, position, anchor));
+}